@@ -0,0 +1,281 @@
+/// USPS street suffix abbreviations (Publication 28, appendix C1), with the
+/// common misspellings counties send us.
+const STREET_SUFFIXES: &[(&str, &str)] = &[
+    ("ALLEY", "ALY"),
+    ("ALLEE", "ALY"),
+    ("AVENUE", "AVE"),
+    ("AVENU", "AVE"),
+    ("AVEN", "AVE"),
+    ("AVN", "AVE"),
+    ("AV", "AVE"),
+    ("BOULEVARD", "BLVD"),
+    ("BOUL", "BLVD"),
+    ("BOULV", "BLVD"),
+    ("CIRCLE", "CIR"),
+    ("CIRC", "CIR"),
+    ("CIRCL", "CIR"),
+    ("COURT", "CT"),
+    ("CRT", "CT"),
+    ("COVE", "CV"),
+    ("CRESCENT", "CRES"),
+    ("DRIVE", "DR"),
+    ("DRIV", "DR"),
+    ("DRV", "DR"),
+    ("EXPRESSWAY", "EXPY"),
+    ("EXPRESS", "EXPY"),
+    ("FREEWAY", "FWY"),
+    ("HIGHWAY", "HWY"),
+    ("HIGHWY", "HWY"),
+    ("HIWAY", "HWY"),
+    ("LANE", "LN"),
+    ("PARKWAY", "PKWY"),
+    ("PARKWY", "PKWY"),
+    ("PKY", "PKWY"),
+    ("PLACE", "PL"),
+    ("PLAZA", "PLZ"),
+    ("POINT", "PT"),
+    ("ROAD", "RD"),
+    ("ROUTE", "RTE"),
+    ("SQUARE", "SQ"),
+    ("SQR", "SQ"),
+    ("STREET", "ST"),
+    ("STRT", "ST"),
+    ("STR", "ST"),
+    ("TERRACE", "TER"),
+    ("TERR", "TER"),
+    ("TRAIL", "TRL"),
+    ("TRAILS", "TRL"),
+    ("TURNPIKE", "TPKE"),
+    ("TRNPK", "TPKE"),
+];
+
+/// USPS secondary unit designators (Publication 28, appendix C2).
+const UNIT_DESIGNATORS: &[(&str, &str)] = &[
+    ("APARTMENT", "APT"),
+    ("BASEMENT", "BSMT"),
+    ("BUILDING", "BLDG"),
+    ("DEPARTMENT", "DEPT"),
+    ("FLOOR", "FL"),
+    ("FRONT", "FRNT"),
+    ("HANGAR", "HNGR"),
+    ("LOBBY", "LBBY"),
+    ("LOWER", "LOWR"),
+    ("OFFICE", "OFC"),
+    ("PENTHOUSE", "PH"),
+    ("REAR", "REAR"),
+    ("ROOM", "RM"),
+    ("SIDE", "SIDE"),
+    ("SPACE", "SPC"),
+    ("SUITE", "STE"),
+    ("TRAILER", "TRLR"),
+    ("UPPER", "UPPR"),
+];
+
+/// The designators that need no unit number after them.
+const UNNUMBERED_DESIGNATORS: &[&str] = &["BSMT", "FRNT", "LBBY", "LOWR", "OFC", "PH", "REAR", "SIDE", "UPPR"];
+
+const DIRECTIONALS: &[(&str, &str)] = &[
+    ("NORTH", "N"),
+    ("SOUTH", "S"),
+    ("EAST", "E"),
+    ("WEST", "W"),
+    ("NORTHEAST", "NE"),
+    ("NORTHWEST", "NW"),
+    ("SOUTHEAST", "SE"),
+    ("SOUTHWEST", "SW"),
+];
+
+/// The abbreviation of `word` in `table`, whether it is spelled out or
+/// already abbreviated.
+fn abbreviate(table: &[(&str, &'static str)], word: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(long, short)| *long == word || *short == word)
+        .map(|(_, short)| *short)
+}
+
+fn is_unit_designator(word: &str) -> bool {
+    word == "UNIT" || abbreviate(UNIT_DESIGNATORS, word).is_some()
+}
+
+/// "4", "4B", "A" and a `#` that is still to be followed by the number.
+fn is_unit_number(word: &str) -> bool {
+    word == "#" || word.chars().count() == 1 || word.chars().any(|c| c.is_ascii_digit())
+}
+
+/// Whether the unit starts at `tokens[at]`: a designator followed by a unit
+/// number, one that needs no number ending the address, or a bare `#`.
+fn starts_unit(tokens: &[&str], at: usize) -> bool {
+    let word = tokens[at];
+    let unnumbered = abbreviate(UNIT_DESIGNATORS, word).is_some_and(|short| UNNUMBERED_DESIGNATORS.contains(&short));
+    match tokens.get(at + 1) {
+        Some(next) => word == "#" || (is_unit_designator(word) && is_unit_number(next)),
+        None => word == "#" || unnumbered,
+    }
+}
+
+/// Puts an address in the USPS standard form so spellings of the same place
+/// compare equal: upper case, punctuation dropped and whitespace collapsed.
+/// Words are only abbreviated where they play their part, so "Court Street"
+/// keeps its name: the street type at the end of the street, a directional
+/// before or after the rest of it, and a unit designator followed by a unit
+/// number. A `#` unit number is split off into its own word unless a
+/// designator precedes it.
+pub fn normalize_address(address: &str) -> String {
+    let cleaned: String = address
+        .to_uppercase()
+        .chars()
+        .map(|c| match c {
+            '.' | ',' | ';' | ':' | '\'' | '"' | '(' | ')' => ' ',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.replace('#', " # ");
+    let tokens: Vec<&str> = cleaned.split_whitespace().collect();
+
+
+    // The house number is not part of the name. A directional, street type
+    // or designator with nothing else left for a name is the name.
+    let name_start = usize::from(tokens.first().is_some_and(|word| word.starts_with(|c: char| c.is_ascii_digit())));
+    let unit_start = (name_start + 1..tokens.len())
+        .find(|&at| starts_unit(&tokens, at))
+        .unwrap_or(tokens.len());
+    let (street, unit) = tokens.split_at(unit_start);
+
+    let mut words = street.to_vec();
+    let mut end = words.len();
+    if end >= name_start + 3
+        && abbreviate(STREET_SUFFIXES, words[end - 2]).is_some()
+        && let Some(short) = abbreviate(DIRECTIONALS, words[end - 1])
+    {
+        words[end - 1] = short;
+        end -= 1;
+    }
+    if end >= name_start + 2
+        && let Some(short) = abbreviate(STREET_SUFFIXES, words[end - 1])
+    {
+        words[end - 1] = short;
+    }
+    if end >= name_start + 3
+        && let Some(short) = abbreviate(DIRECTIONALS, words[name_start])
+    {
+        words[name_start] = short;
+    }
+
+    for (at, word) in unit.iter().enumerate() {
+        let word = match abbreviate(UNIT_DESIGNATORS, word) {
+            Some(short) if starts_unit(unit, at) => short,
+            _ => word,
+        };
+
+        // "APT #4" is just "APT 4".
+        if word == "#" && at > 0 && is_unit_designator(unit[at - 1]) {
+            continue;
+        }
+        words.push(word);
+    }
+
+    words.join(" ")
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// 1.0 for identical addresses, falling towards 0.0 as the edit distance
+/// approaches the length of the longer one.
+pub fn similarity(a: &str, b: &str) -> (usize, f64) {
+    let distance = edit_distance(a, b);
+    let longest = a.chars().count().max(b.chars().count());
+    let score = match longest {
+        0 => 1.0,
+        longest => 1.0 - distance as f64 / longest as f64,
+    };
+
+    (distance, score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_address_abbreviates_and_cleans_up() {
+        let cases = [
+            ("123 Main Street", "123 MAIN ST"),
+            ("123  main   st.", "123 MAIN ST"),
+            ("9 North Elm Avenue, Apartment 4", "9 N ELM AVE APT 4"),
+            ("9 N. Elm Ave., Apt. #4", "9 N ELM AVE APT 4"),
+            ("500 Southwest Parkway Suite 210", "500 SOUTHWEST PKWY STE 210"),
+            ("500 Southwest Main Parkway", "500 SW MAIN PKWY"),
+            ("77 O'Brien Boulevard (rear)", "77 O BRIEN BLVD REAR"),
+            ("12 Oak Ln Unit #3", "12 OAK LN UNIT 3"),
+            ("", ""),
+        ];
+
+        for (address, normalized) in cases {
+            assert_eq!(normalize_address(address), normalized, "{address:?}");
+        }
+    }
+
+    #[test]
+    fn normalize_address_only_abbreviates_words_in_their_place() {
+        let cases = [
+            ("1 Court Street", "1 COURT ST"),
+            ("1 Park Avenue", "1 PARK AVE"),
+            ("1 North Street", "1 NORTH ST"),
+            ("1 Main Street North", "1 MAIN ST N"),
+            ("1 North Street South", "1 NORTH ST S"),
+            ("1 West Court Lane East", "1 W COURT LN E"),
+            ("1 Suite Road", "1 SUITE RD"),
+            ("1 Front Street Front", "1 FRONT ST FRNT"),
+            ("1 Rear Road", "1 REAR RD"),
+            ("1 Front Street Floor 2", "1 FRONT ST FL 2"),
+            ("Avenue Road", "AVENUE RD"),
+        ];
+
+        for (address, normalized) in cases {
+            assert_eq!(normalize_address(address), normalized, "{address:?}");
+        }
+    }
+
+    #[test]
+    fn normalize_address_splits_off_a_bare_unit_number() {
+        assert_eq!(normalize_address("40 Pine Road #12"), "40 PINE RD # 12");
+        assert_eq!(normalize_address("40 Pine Road#12"), "40 PINE RD # 12");
+    }
+
+    #[test]
+    fn normalize_address_is_idempotent() {
+        for address in ["9 North Elm Avenue, Apt #4", "40 Pine Road #12", "1 Main St", "1 Court Street North"] {
+            let once = normalize_address(address);
+            assert_eq!(normalize_address(&once), once);
+        }
+    }
+
+    #[test]
+    fn similarity_scores_edit_distance_over_length() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(similarity("", ""), (0, 1.0));
+        assert_eq!(similarity("1 MAIN ST", "1 MAIN ST"), (0, 1.0));
+
+        let (distance, score) = similarity("1 MAIN ST", "1 MAIN AVE");
+        assert_eq!(distance, 3);
+        assert!((score - 0.7).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+
+use actix_web::{HttpRequest, HttpResponse, ResponseError, http::StatusCode};
+use heed::MdbError;
+use serde_json::{Map, Value, json};
+
+use crate::{concurrency::etag, struct_definitions::FilterError};
+
+/// Every handler error, rendered as a JSON body of `code`, `message` and
+/// `field`, plus whatever extra context the variant carries.
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound {
+        message: String,
+    },
+    Validation {
+        code: &'static str,
+        field: Option<String>,
+        message: String,
+    },
+    Conflict {
+        field: Option<String>,
+        message: String,
+        details: Map<String, Value>,
+    },
+    PreconditionFailed {
+        key: String,
+        revision: u64,
+    },
+    Storage(heed::Error),
+    MapFull,
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn not_found(message: impl Into<String>) -> Self {
+        ApiError::NotFound {
+            message: message.into(),
+        }
+    }
+
+    pub fn validation(field: impl Into<String>, message: impl Into<String>) -> Self {
+        ApiError::Validation {
+            code: "invalid_value",
+            field: Some(field.into()),
+            message: message.into(),
+        }
+    }
+
+    pub fn conflict(field: impl Into<String>, message: impl Into<String>) -> Self {
+        ApiError::Conflict {
+            field: Some(field.into()),
+            message: message.into(),
+            details: Map::new(),
+        }
+    }
+
+    pub fn permit_conflict(permit_number: &str, existing_key: &str) -> Self {
+        ApiError::conflict(
+            "permit_number",
+            format!("permit number '{permit_number}' is already used by {existing_key}"),
+        )
+        .with_detail("existing_key", existing_key)
+    }
+
+    /// Adds a field to the body of a conflict. Other variants are unchanged.
+    pub fn with_detail(mut self, name: &str, value: impl Into<Value>) -> Self {
+        if let ApiError::Conflict { details, .. } = &mut self {
+            details.insert(name.to_string(), value.into());
+        }
+        self
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound { .. } => "not_found",
+            ApiError::Validation { code, .. } => code,
+            ApiError::Conflict { .. } => "conflict",
+            ApiError::PreconditionFailed { .. } => "precondition_failed",
+            ApiError::Storage(_) => "storage_error",
+            ApiError::MapFull => "map_full",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    fn field(&self) -> Option<&str> {
+        match self {
+            ApiError::Validation { field, .. } | ApiError::Conflict { field, .. } => field.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The JSON body on its own, for reports that list several errors.
+    pub fn body(&self) -> Value {
+        let mut body = json!({
+            "code": self.code(),
+            "message": self.to_string(),
+            "field": self.field()
+        });
+
+        match self {
+            ApiError::Conflict { details, .. } => {
+                for (name, value) in details {
+                    body[name] = value.clone();
+                }
+            }
+            ApiError::PreconditionFailed { revision, .. } => body["current_revision"] = json!(revision),
+            _ => {}
+        }
+
+        body
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::NotFound { message }
+            | ApiError::Validation { message, .. }
+            | ApiError::Conflict { message, .. } => write!(f, "{message}"),
+            ApiError::PreconditionFailed { key, .. } => write!(f, "{key} has been modified since it was read"),
+            ApiError::Storage(_) => write!(f, "Database error"),
+            ApiError::MapFull => write!(f, "The database has run out of space"),
+            ApiError::Internal(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound { .. } => StatusCode::NOT_FOUND,
+            ApiError::Validation { .. } => StatusCode::BAD_REQUEST,
+            ApiError::Conflict { .. } => StatusCode::CONFLICT,
+            ApiError::PreconditionFailed { .. } => StatusCode::PRECONDITION_FAILED,
+            ApiError::MapFull => StatusCode::INSUFFICIENT_STORAGE,
+            ApiError::Storage(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ApiError::Storage(e) => eprintln!("Database error: {e}"),
+            ApiError::Internal(message) => eprintln!("Internal error: {message}"),
+            _ => {}
+        }
+
+        let mut response = HttpResponse::build(self.status_code());
+        if let ApiError::PreconditionFailed { revision, .. } = self {
+            response.insert_header(etag(*revision));
+        }
+        response.json(self.body())
+    }
+}
+
+/// Error handler for the `Json`, `Query` and `Path` extractor configs, so a
+/// malformed request gets the same JSON body as any other validation error.
+pub fn extractor_error<E: fmt::Display>(
+    field: &'static str,
+) -> impl Fn(E, &HttpRequest) -> actix_web::Error + Send + Sync + 'static {
+    move |e, _| ApiError::validation(field, e.to_string()).into()
+}
+
+impl From<heed::Error> for ApiError {
+    fn from(e: heed::Error) -> Self {
+        match e {
+            heed::Error::Mdb(MdbError::MapFull) => ApiError::MapFull,
+            e => ApiError::Storage(e),
+        }
+    }
+}
+
+impl From<FilterError> for ApiError {
+    fn from(e: FilterError) -> Self {
+        ApiError::Validation {
+            code: e.code,
+            field: Some(e.field),
+            message: e.message,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::future::{Ready, ready};
+
+use actix_web::{FromRequest, HttpRequest, dev::Payload};
+use heed::{RoTxn, RwTxn};
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+
+use crate::struct_definitions::{AuditAction, AuditEntry, DBHandles, FieldChange};
+
+pub const MAIN_DB: &str = "main_db";
+pub const PROCESSING_STATE_DB: &str = "processing_state_db";
+pub const PAYMENTS_DB: &str = "payments_db";
+pub const WORKFLOW_STAGES_DB: &str = "workflow_stages";
+
+/// Who made a change and through which endpoint. The actor is taken from the
+/// `X-Actor` header.
+pub struct AuditContext {
+    pub actor: String,
+    pub endpoint: String,
+}
+
+impl FromRequest for AuditContext {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let actor = req
+            .headers()
+            .get("X-Actor")
+            .and_then(|actor| actor.to_str().ok())
+            .filter(|actor| !actor.trim().is_empty())
+            .unwrap_or("anonymous")
+            .to_string();
+        let endpoint = format!(
+            "{} {}",
+            req.method(),
+            req.match_pattern().unwrap_or_else(|| req.path().to_string())
+        );
+
+        ready(Ok(AuditContext { actor, endpoint }))
+    }
+}
+
+fn to_fields<T: Serialize>(value: Option<&T>) -> Map<String, Value> {
+    match value.map(serde_json::to_value) {
+        Some(Ok(Value::Object(fields))) => fields,
+        _ => Map::new(),
+    }
+}
+
+/// Field-by-field difference between two versions of a row. Values are kept
+/// as JSON text since bincode can't store a `serde_json::Value`.
+pub fn diff<T: Serialize>(before: Option<&T>, after: Option<&T>) -> Vec<FieldChange> {
+    let before = to_fields(before);
+    let after = to_fields(after);
+
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    fields
+        .into_iter()
+        .filter(|field| before.get(*field) != after.get(*field))
+        .map(|field| FieldChange {
+            field: field.to_string(),
+            before: before.get(field).map(Value::to_string),
+            after: after.get(field).map(Value::to_string),
+        })
+        .collect()
+}
+
+/// One row-level change to be written to the audit log.
+pub struct Change<'a, T> {
+    pub database: &'a str,
+    pub key: &'a str,
+    pub previous_key: Option<&'a str>,
+    pub action: AuditAction,
+    pub before: Option<&'a T>,
+    pub after: Option<&'a T>,
+}
+
+impl<'a, T> Change<'a, T> {
+    pub fn created(database: &'a str, key: &'a str, after: &'a T) -> Self {
+        Change {
+            database,
+            key,
+            previous_key: None,
+            action: AuditAction::Create,
+            before: None,
+            after: Some(after),
+        }
+    }
+
+    pub fn updated(database: &'a str, key: &'a str, before: &'a T, after: &'a T) -> Self {
+        Change {
+            database,
+            key,
+            previous_key: None,
+            action: AuditAction::Update,
+            before: Some(before),
+            after: Some(after),
+        }
+    }
+
+    pub fn deleted(database: &'a str, key: &'a str, before: &'a T) -> Self {
+        Change {
+            database,
+            key,
+            previous_key: None,
+            action: AuditAction::Delete,
+            before: Some(before),
+            after: None,
+        }
+    }
+
+    pub fn with_action(mut self, action: AuditAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    pub fn with_previous_key(mut self, previous_key: Option<&'a str>) -> Self {
+        self.previous_key = previous_key.filter(|previous_key| *previous_key != self.key);
+        self
+    }
+}
+
+/// Appends an entry to `audit_log` inside the caller's write transaction and
+/// indexes it under the row's key, and its previous key when it was re-keyed.
+pub fn record_change<T: Serialize>(
+    db_handles: &DBHandles,
+    wtxn: &mut RwTxn,
+    context: &AuditContext,
+    change: Change<T>,
+) -> heed::Result<()> {
+    let timestamp = chrono::Utc::now().naive_utc();
+    let id = format!("{timestamp:?}-{}", uuid::Uuid::new_v4());
+
+    let entry = AuditEntry {
+        key: change.key.to_string(),
+        database: change.database.to_string(),
+        action: change.action,
+        endpoint: context.endpoint.to_owned(),
+        actor: context.actor.to_owned(),
+        timestamp,
+        changes: diff(change.before, change.after),
+    };
+    db_handles.audit_log.put(wtxn, &id, &entry)?;
+
+    for indexed_key in std::iter::once(change.key).chain(change.previous_key) {
+        db_handles.audit_key_index.put(wtxn, &audit_key(indexed_key, &id), &())?;
+    }
+
+    Ok(())
+}
+
+/// The `audit_key_index` entry of audit entry `id` under a row's `key`.
+/// Ids start with their timestamp, so a key's entries sort oldest first.
+pub fn audit_key(key: &str, id: &str) -> String {
+    format!("{key}\0{id}")
+}
+
+/// Ids of the audit entries of a row, oldest first.
+pub fn audit_ids(rtxn: &RoTxn, db_handles: &DBHandles, key: &str) -> heed::Result<Vec<String>> {
+    let prefix = audit_key(key, "");
+    let mut ids = vec![];
+    for entry in db_handles.audit_key_index.prefix_iter(rtxn, &prefix)? {
+        let (indexed, ()) = entry?;
+        ids.push(indexed[prefix.len()..].to_string());
+    }
+
+    Ok(ids)
+}
+
+pub fn audit_entry_json(id: &str, entry: &AuditEntry) -> Value {
+    let parse = |value: &Option<String>| {
+        value
+            .as_deref()
+            .and_then(|value| serde_json::from_str::<Value>(value).ok())
+    };
+
+    json!({
+        "id": id,
+        "key": entry.key,
+        "database": entry.database,
+        "action": entry.action,
+        "endpoint": entry.endpoint,
+        "actor": entry.actor,
+        "timestamp": entry.timestamp,
+        "changes": entry
+            .changes
+            .iter()
+            .map(|change| json!({
+                "field": change.field,
+                "before": parse(&change.before),
+                "after": parse(&change.after)
+            }))
+            .collect::<Vec<_>>()
+    })
+}
@@ -0,0 +1,72 @@
+use std::future::{Ready, ready};
+
+use actix_web::{
+    FromRequest, HttpRequest,
+    dev::Payload,
+    http::header::{self, ETag, EntityTag, Header, IfMatch},
+};
+
+use crate::api_error::ApiError;
+
+/// Strong entity tag for a stored row's revision counter.
+pub fn etag(revision: u64) -> ETag {
+    ETag(EntityTag::new_strong(revision.to_string()))
+}
+
+/// ETag for a read of per-permit row lists that came down to a single row.
+/// Reads of several rows carry each row's `revision` in the body instead.
+pub fn single_row_etag<K, T>(groups: &[Vec<(K, T)>], revision: impl Fn(&T) -> u64) -> Option<ETag> {
+    match groups {
+        [rows] => match rows.as_slice() {
+            [(_, row)] => Some(etag(revision(row))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The request's `If-Match` precondition, if it sent one.
+pub struct IfMatchRevision(Option<IfMatch>);
+
+impl FromRequest for IfMatchRevision {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        if !req.headers().contains_key(header::IF_MATCH) {
+            return ready(Ok(IfMatchRevision(None)));
+        }
+
+        ready(
+            IfMatch::parse(req)
+                .map(|if_match| IfMatchRevision(Some(if_match)))
+                .map_err(|_| ApiError::validation("If-Match", "Malformed If-Match header")),
+        )
+    }
+}
+
+impl IfMatchRevision {
+    /// Whether an update may be applied on top of the stored `revision`.
+    /// Requests without `If-Match` are always allowed.
+    pub fn allows(&self, revision: u64) -> bool {
+        match &self.0 {
+            None | Some(IfMatch::Any) => true,
+            Some(IfMatch::Items(tags)) => {
+                let current = EntityTag::new_strong(revision.to_string());
+                tags.iter().any(|tag| tag.strong_eq(&current))
+            }
+        }
+    }
+
+    /// Fails with 412 Precondition Failed when `key` has moved past the
+    /// revision the client last read.
+    pub fn check(&self, key: &str, revision: u64) -> Result<(), ApiError> {
+        match self.allows(revision) {
+            true => Ok(()),
+            false => Err(ApiError::PreconditionFailed {
+                key: key.to_string(),
+                revision,
+            }),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use heed::{Env, RoTxn, RwTxn};
+use serde::Serialize;
+
+use crate::struct_definitions::{CounterKey, DBHandles, DBSchema, KeySchema, Status};
+
+/// The `counters` entry a record is counted under.
+pub fn counter_key(record: &DBSchema) -> CounterKey {
+    CounterKey {
+        index_key: KeySchema::from(record),
+        month: record.opened.format("%Y-%m").to_string(),
+    }
+}
+
+/// Adds `delta` to a counter, removing it once it reaches zero.
+fn adjust(db_handles: &DBHandles, wtxn: &mut RwTxn, key: &CounterKey, delta: i64) -> heed::Result<()> {
+    let count = db_handles.counters.get(wtxn, key)?.unwrap_or(0);
+    match count.saturating_add_signed(delta) {
+        0 => db_handles.counters.delete(wtxn, key).map(|_| ()),
+        count => db_handles.counters.put(wtxn, key, &count),
+    }
+}
+
+pub fn count_record(db_handles: &DBHandles, wtxn: &mut RwTxn, record: &DBSchema) -> heed::Result<()> {
+    adjust(db_handles, wtxn, &counter_key(record), 1)
+}
+
+pub fn uncount_record(db_handles: &DBHandles, wtxn: &mut RwTxn, record: &DBSchema) -> heed::Result<()> {
+    adjust(db_handles, wtxn, &counter_key(record), -1)
+}
+
+/// Counts a batch of new records, touching each counter once.
+pub fn count_records<'a>(
+    db_handles: &DBHandles,
+    wtxn: &mut RwTxn,
+    records: impl Iterator<Item = &'a DBSchema>,
+) -> heed::Result<()> {
+    let mut deltas: HashMap<CounterKey, i64> = HashMap::new();
+    for record in records {
+        *deltas.entry(counter_key(record)).or_default() += 1;
+    }
+    for (key, delta) in deltas {
+        adjust(db_handles, wtxn, &key, delta)?;
+    }
+
+    Ok(())
+}
+
+/// What the counters should hold, counted from `main_db`.
+fn recount(rtxn: &RoTxn, db_handles: &DBHandles) -> heed::Result<HashMap<CounterKey, u64>> {
+    let mut counts: HashMap<CounterKey, u64> = HashMap::new();
+    for entry in db_handles.main_db.iter(rtxn)? {
+        let (_, record) = entry?;
+        *counts.entry(counter_key(&record)).or_default() += 1;
+    }
+    Ok(counts)
+}
+
+/// Replaces every counter with a fresh count of `main_db`, returning how
+/// many counters were written.
+pub fn rebuild_counters(env: &Env, db_handles: &DBHandles) -> heed::Result<usize> {
+    let mut wtxn = env.write_txn()?;
+    let counts = recount(&wtxn, db_handles)?;
+
+    db_handles.counters.clear(&mut wtxn)?;
+    for (key, count) in &counts {
+        db_handles.counters.put(&mut wtxn, key, count)?;
+    }
+    wtxn.commit()?;
+
+    Ok(counts.len())
+}
+
+/// A counter that disagrees with `main_db`.
+#[derive(Debug, Serialize)]
+pub struct CounterDrift {
+    pub client: String,
+    pub county: String,
+    pub county_status: Status,
+    pub month: String,
+    pub stored: u64,
+    pub actual: u64,
+}
+
+impl CounterDrift {
+    fn new(key: CounterKey, stored: u64, actual: u64) -> Self {
+        CounterDrift {
+            client: key.index_key.client,
+            county: key.index_key.county,
+            county_status: key.index_key.county_status,
+            month: key.month,
+            stored,
+            actual,
+        }
+    }
+}
+
+/// Recounts `main_db` and lists every counter that is off, including ones
+/// that are missing or should not exist.
+pub fn check_counters(rtxn: &RoTxn, db_handles: &DBHandles) -> heed::Result<Vec<CounterDrift>> {
+    let mut actual = recount(rtxn, db_handles)?;
+    let mut drift = vec![];
+
+    for entry in db_handles.counters.iter(rtxn)? {
+        let (key, stored) = entry?;
+        let count = actual.remove(&key).unwrap_or(0);
+        if count != stored {
+            drift.push((key, stored, count));
+        }
+    }
+    drift.extend(actual.into_iter().map(|(key, count)| (key, 0, count)));
+    drift.sort();
+
+    Ok(drift
+        .into_iter()
+        .map(|(key, stored, actual)| CounterDrift::new(key, stored, actual))
+        .collect())
+}
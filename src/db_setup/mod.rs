@@ -1,16 +1,21 @@
 use std::{collections::HashSet, sync::Arc};
 use heed::{types::*, Database, Env};
-use crate::struct_definitions::{DBHandles, DBSchema, KeySchema, Payments, ProcessingStatusSchema};
+use crate::audit::audit_key;
+use crate::migrations::run_migrations;
+use crate::counters::rebuild_counters;
+use crate::search::rebuild_search_index;
+use crate::workflow::{rebuild_assignee_index, rebuild_current_states};
+use crate::struct_definitions::{AuditEntry, CounterKey, CurrentProcessing, DBHandles, DBSchema, KeySchema, Payments, ProcessStatus, ProcessingStatusSchema, StatusTransition, Tombstone};
 
 pub fn setup_db(env: Arc<Env>) -> Result<DBHandles, Box<dyn std::error::Error>> {
-    let db_path = std::path::Path::new("database");
-
-    if !db_path.exists() {
-        std::fs::create_dir_all(db_path)?;
-    }
-
+    let mut build_search_index = false;
+    let mut build_counters = false;
+    let mut build_current_states = false;
+    let mut build_assignee_index = false;
     {
         let mut wtxn = env.write_txn()?;
+
+        run_migrations(&env, &mut wtxn)?;
         
         if env
             .open_database::<Str, SerdeBincode<DBSchema>>(&wtxn, Some("main_db"))?
@@ -43,6 +48,149 @@ pub fn setup_db(env: Arc<Env>) -> Result<DBHandles, Box<dyn std::error::Error>>
             println!("Creating Payments db...");
             env.create_database::<Str, SerdeBincode<Payments>>(&mut wtxn, Some("payments_db"))?;
         }
+
+        if env
+            .open_database::<Str, Str>(&wtxn, Some("permit_index"))?
+            .is_none()
+        {
+            println!("Creating permit_index...");
+            let permit_index = env.create_database::<Str, Str>(&mut wtxn, Some("permit_index"))?;
+            let main_db = env
+                .open_database::<Str, SerdeBincode<DBSchema>>(&wtxn, Some("main_db"))?
+                .unwrap();
+
+            let mut entries = vec![];
+            for entry in main_db.iter(&wtxn)? {
+                let (key, record) = entry?;
+                entries.push((record.permit_number, key.to_string()));
+            }
+            for (permit_number, key) in entries {
+                permit_index.put(&mut wtxn, &permit_number, &key)?;
+            }
+        }
+
+        if env
+            .open_database::<Str, SerdeBincode<Tombstone>>(&wtxn, Some("deleted_records"))?
+            .is_none()
+        {
+            println!("Creating deleted_records...");
+            env.create_database::<Str, SerdeBincode<Tombstone>>(&mut wtxn, Some("deleted_records"))?;
+        }
+
+        if env
+            .open_database::<Str, Str>(&wtxn, Some("deleted_permit_index"))?
+            .is_none()
+        {
+            println!("Creating deleted_permit_index...");
+            let deleted_permit_index = env.create_database::<Str, Str>(&mut wtxn, Some("deleted_permit_index"))?;
+            let deleted_db = env
+                .open_database::<Str, SerdeBincode<Tombstone>>(&wtxn, Some("deleted_records"))?
+                .unwrap();
+
+            let mut entries = vec![];
+            for entry in deleted_db.iter(&wtxn)? {
+                let (key, tombstone) = entry?;
+                entries.push((tombstone.record.permit_number, key.to_string()));
+            }
+            for (permit_number, key) in entries {
+                deleted_permit_index.put(&mut wtxn, &permit_number, &key)?;
+            }
+        }
+
+        if env
+            .open_database::<Str, SerdeBincode<AuditEntry>>(&wtxn, Some("audit_log"))?
+            .is_none()
+        {
+            println!("Creating audit_log...");
+            env.create_database::<Str, SerdeBincode<AuditEntry>>(&mut wtxn, Some("audit_log"))?;
+        }
+
+        if env
+            .open_database::<Str, Unit>(&wtxn, Some("audit_key_index"))?
+            .is_none()
+        {
+            println!("Creating audit_key_index...");
+            let audit_key_index = env.create_database::<Str, Unit>(&mut wtxn, Some("audit_key_index"))?;
+
+            // audit_index held every entry id of a key in one value.
+            if let Some(audit_index) = env.open_database::<Str, SerdeBincode<Vec<String>>>(&wtxn, Some("audit_index"))? {
+                let mut entries = vec![];
+                for entry in audit_index.iter(&wtxn)? {
+                    let (key, ids) = entry?;
+                    entries.extend(ids.into_iter().map(|id| audit_key(key, &id)));
+                }
+                for entry in entries {
+                    audit_key_index.put(&mut wtxn, &entry, &())?;
+                }
+                audit_index.clear(&mut wtxn)?;
+            }
+        }
+
+        if env
+            .open_database::<Str, SerdeBincode<u32>>(&wtxn, Some("search_postings"))?
+            .is_none()
+        {
+            println!("Creating search_postings...");
+            env.create_database::<Str, SerdeBincode<u32>>(&mut wtxn, Some("search_postings"))?;
+            build_search_index = true;
+
+            // search_index held every posting of a term in one value.
+            if let Some(search_index) = env.open_database::<Str, DecodeIgnore>(&wtxn, Some("search_index"))? {
+                search_index.clear(&mut wtxn)?;
+            }
+        }
+
+        if env
+            .open_database::<SerdeBincode<CounterKey>, SerdeBincode<u64>>(&wtxn, Some("counters"))?
+            .is_none()
+        {
+            println!("Creating counters...");
+            env.create_database::<SerdeBincode<CounterKey>, SerdeBincode<u64>>(&mut wtxn, Some("counters"))?;
+            build_counters = true;
+        }
+
+        if env
+            .open_database::<Str, SerdeBincode<Vec<StatusTransition>>>(&wtxn, Some("status_history"))?
+            .is_none()
+        {
+            println!("Creating status_history...");
+            env.create_database::<Str, SerdeBincode<Vec<StatusTransition>>>(&mut wtxn, Some("status_history"))?;
+        }
+
+        if env
+            .open_database::<Str, Str>(&wtxn, Some("due_index"))?
+            .is_none()
+        {
+            println!("Creating due_index...");
+            env.create_database::<Str, Str>(&mut wtxn, Some("due_index"))?;
+            build_current_states = true;
+        }
+
+        if env
+            .open_database::<Str, SerdeBincode<HashSet<String>>>(&wtxn, Some("assignee_index"))?
+            .is_none()
+        {
+            println!("Creating assignee_index...");
+            env.create_database::<Str, SerdeBincode<HashSet<String>>>(&mut wtxn, Some("assignee_index"))?;
+            build_assignee_index = true;
+        }
+
+        if env
+            .open_database::<Str, SerdeBincode<CurrentProcessing>>(&wtxn, Some("current_processing"))?
+            .is_none()
+        {
+            println!("Creating current_processing...");
+            env.create_database::<Str, SerdeBincode<CurrentProcessing>>(&mut wtxn, Some("current_processing"))?;
+            build_current_states = true;
+        }
+
+        if env
+            .open_database::<Str, SerdeBincode<Vec<ProcessStatus>>>(&wtxn, Some("workflow_stages"))?
+            .is_none()
+        {
+            println!("Creating workflow_stages...");
+            env.create_database::<Str, SerdeBincode<Vec<ProcessStatus>>>(&mut wtxn, Some("workflow_stages"))?;
+        }
         
         wtxn.commit()?
     }
@@ -65,12 +213,90 @@ pub fn setup_db(env: Arc<Env>) -> Result<DBHandles, Box<dyn std::error::Error>>
         .open_database(&rtxn, Some("payments_db"))?
         .unwrap();
 
+    let permit_index = env
+        .open_database(&rtxn, Some("permit_index"))?
+        .unwrap();
+
+    let deleted_db = env
+        .open_database(&rtxn, Some("deleted_records"))?
+        .unwrap();
+
+    let deleted_permit_index = env
+        .open_database(&rtxn, Some("deleted_permit_index"))?
+        .unwrap();
+
+    let audit_log = env
+        .open_database(&rtxn, Some("audit_log"))?
+        .unwrap();
+
+    let audit_key_index = env
+        .open_database(&rtxn, Some("audit_key_index"))?
+        .unwrap();
+
+    let search_postings = env
+        .open_database(&rtxn, Some("search_postings"))?
+        .unwrap();
+
+    let counters = env
+        .open_database(&rtxn, Some("counters"))?
+        .unwrap();
+
+    let status_history = env
+        .open_database(&rtxn, Some("status_history"))?
+        .unwrap();
+
+    let due_index = env
+        .open_database(&rtxn, Some("due_index"))?
+        .unwrap();
+
+    let assignee_index = env
+        .open_database(&rtxn, Some("assignee_index"))?
+        .unwrap();
+
+    let current_processing = env
+        .open_database(&rtxn, Some("current_processing"))?
+        .unwrap();
+
+    let workflow_stages = env
+        .open_database(&rtxn, Some("workflow_stages"))?
+        .unwrap();
+
     drop(rtxn);
-        
-    Ok(DBHandles {
+
+    let db_handles = DBHandles {
         main_db,
         composite_index,
         processing_state,
-        payments_db
-    })
+        payments_db,
+        permit_index,
+        deleted_db,
+        deleted_permit_index,
+        audit_log,
+        audit_key_index,
+        search_postings,
+        counters,
+        status_history,
+        due_index,
+        assignee_index,
+        current_processing,
+        workflow_stages
+    };
+
+    if build_search_index {
+        rebuild_search_index(&env, &db_handles)?;
+    }
+
+    if build_counters {
+        rebuild_counters(&env, &db_handles)?;
+    }
+
+    if build_current_states {
+        rebuild_current_states(&env, &db_handles)?;
+    }
+
+    if build_assignee_index {
+        rebuild_assignee_index(&env, &db_handles)?;
+    }
+
+    Ok(db_handles)
 }
\ No newline at end of file
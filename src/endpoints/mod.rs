@@ -1,19 +1,41 @@
-use std::{
-    collections::{HashMap, HashSet},
-    str::FromStr,
-};
+use std::{collections::{HashMap, HashSet}, ops::Bound};
 
-use actix_web::{HttpResponse, Responder, delete, get, post, put, web};
-use chrono::{NaiveDate, NaiveDateTime};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, put, web};
+use chrono::NaiveDateTime;
 use heed::RoTxn;
 use serde_json::json;
 
 use crate::{
-    handle,
-    helper_functions::{data_with_response_time, data_with_response_time_for_slice, loader},
+    address::{normalize_address, similarity},
+    api_error::ApiError,
+    audit::{
+        AuditContext, Change, MAIN_DB, PAYMENTS_DB, PROCESSING_STATE_DB, WORKFLOW_STAGES_DB, audit_entry_json,
+        audit_ids, record_change,
+    },
+    concurrency::{IfMatchRevision, etag, single_row_etag},
+    counters::check_counters,
+    lifecycle::record_transitions,
+    export::{EXPORT_PARAMS, ExportFormat, parse_columns, parse_include_deleted, stream_matches},
+    import::ColumnMapping,
+    helper_functions::{
+        child_keys, data_with_response_time, delete_child_rows, index_record, index_records, insert_new_record, is_child_key, loader,
+        parse_bulk_items, permit_number_owner, unindex_record,
+    },
+    query_engine::collect_deleted_matches,
+    search::{TextQuery, page_of_results},
+    stages::{county_stages, parse_stages, permit_county, validate_stage},
+    stats::{GroupBy, count_permits},
+    workflow::{
+        assignee_queue, current_processing_state, due_states, group_by_assignee, index_assignee, index_current_state, parse_queue_sort, parse_within,
+        processing_status_filter, processing_status_params, unindex_assignee, unindex_current_state,
+    },
+    listing::ListingParams,
+    pagination::{DEFAULT_PAGE_SIZE, PageRequest, SortOrder, page_of_matches},
+    validation::{date_error_message, parse_datetime, require_date_range, validate_payment},
     struct_definitions::{
-        DBSchema, DBdata, DbEnv, KeySchema, Payments, ProcessingStatusSchema, Status,
-        UpdateDBSchema, UpdatePayment, UpdateProcessingStatusSchema,
+        AppConfig, AuditAction, AuditQuery, BulkMode, BulkOptions, BulkRecord, DBSchema, DateRangeQuery, DBdata, DbEnv, DeleteOptions, DeletePolicy, Payments, PurgeOptions,
+        PermitFilterQuery, ReadOptions, ProcessingStatusSchema, RecordFilter, RecordListQuery, SearchRequest, SimilarQuery, StatsQuery, DueQuery, ProcessStatus, ProcessingReadOptions, QueueQuery, StatusField, StatusTransition, TextSearchQuery,
+        Tombstone, UpdateDBSchema, UpdatePayment, UpdateProcessingStatusSchema,
     },
 };
 
@@ -21,45 +43,40 @@ use crate::{
 pub async fn create_record(
     db_env: web::Data<DbEnv>,
     db_handles: web::Data<DBdata>,
+    audit: AuditContext,
     data: web::Json<DBSchema>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
-    let mut wtxn = handle!(db_env.env.write_txn());
+) -> Result<impl Responder, ApiError> {
+    let mut wtxn = db_env.env.write_txn()?;
     let uuid = uuid::Uuid::new_v4().to_string();
     let uuid = format!("{:?}-{}", data.opened, uuid);
 
-    let key = KeySchema {
-        client: data.client.to_owned(),
-        county: data.county.to_owned(),
-        county_status: data.county_status.clone(),
-    };
-
-    let uuid_set = handle!(db_handles.db_data.composite_index.get(&wtxn, &key));
-    handle!(db_handles.db_data.main_db.put(&mut wtxn, &uuid, &data));
-
-    if let Some(mut prev_set) = uuid_set {
-        prev_set.insert(uuid.to_owned());
-        handle!(
-            db_handles
-                .db_data
-                .composite_index
-                .put(&mut wtxn, &key, &mut prev_set)
-        );
-    } else {
-        let mut new_set: HashSet<String> = HashSet::new();
-        new_set.insert(uuid.to_owned());
-        handle!(
-            db_handles
-                .db_data
-                .composite_index
-                .put(&mut wtxn, &key, &mut new_set)
-        );
+    if let Some(existing_key) = permit_number_owner(
+        &db_handles.db_data,
+        &wtxn,
+        &data.permit_number,
+        None
+    )? {
+        return Err(ApiError::permit_conflict(&data.permit_number, &existing_key));
     }
 
+    let mut data = data.into_inner();
+    data.revision = 1;
+    data.normalized_address = normalize_address(&data.address);
+
+    db_handles.db_data.main_db.put(&mut wtxn, &uuid, &data)?;
+    index_record(&db_handles.db_data, &mut wtxn, &uuid, &data)?;
+    record_change(
+        &db_handles.db_data,
+        &mut wtxn,
+        &audit,
+        Change::created(MAIN_DB, &uuid, &data)
+    )?;
+
     let start = std::time::Instant::now();
-    handle!(wtxn.commit());
+    wtxn.commit()?;
     let duration = start.elapsed();
 
-    Ok(HttpResponse::Ok().body(format!(
+    Ok(HttpResponse::Ok().insert_header(etag(data.revision)).body(format!(
         "Successfully Loaded into DataBase, uuid is: {}\nResponse Time: {}",
         uuid,
         duration.as_micros()
@@ -70,29 +87,47 @@ pub async fn create_record(
 pub async fn create_processing_state(
     db_env: web::Data<DbEnv>,
     db_handles: web::Data<DBdata>,
+    audit: AuditContext,
     data: web::Json<ProcessingStatusSchema>,
     path: web::Path<String>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
-    let mut wtxn = handle!(db_env.env.write_txn());
+) -> Result<impl Responder, ApiError> {
+    let mut wtxn = db_env.env.write_txn()?;
     let key = path.into_inner();
 
+    let county = permit_county(&wtxn, &db_handles.db_data, &key)?;
+    validate_stage(&wtxn, &db_handles.db_data, county.as_deref(), &data.processing_status)?;
+
+    let indexing_key = format!("{key}-{:?}", data.last_modified);
+    let previous = db_handles.db_data.processing_state.get(&wtxn, &indexing_key)?;
+    unindex_current_state(&db_handles.db_data, &mut wtxn, &key)?;
+
     let processing_state_data = ProcessingStatusSchema {
         processing_status: data.processing_status.to_owned(),
         due_date: data.due_date,
         last_modified: data.last_modified,
         assigned_to: data.assigned_to.to_owned(),
+        revision: previous.as_ref().map_or(1, |previous| previous.revision + 1),
     };
 
-    let indexing_key = format!("{key}-{:?}", data.last_modified);
-
-    handle!(db_handles.db_data.processing_state.put(
+    if let Some(previous) = &previous {
+        unindex_assignee(&db_handles.db_data, &mut wtxn, &indexing_key, previous)?;
+    }
+    db_handles.db_data.processing_state.put(
         &mut wtxn,
         &indexing_key,
         &processing_state_data
-    ));
+    )?;
+    index_assignee(&db_handles.db_data, &mut wtxn, &indexing_key, &processing_state_data)?;
+    index_current_state(&db_handles.db_data, &mut wtxn, &key)?;
+
+    let change = match &previous {
+        Some(previous) => Change::updated(PROCESSING_STATE_DB, &indexing_key, previous, &processing_state_data),
+        None => Change::created(PROCESSING_STATE_DB, &indexing_key, &processing_state_data),
+    };
+    record_change(&db_handles.db_data, &mut wtxn, &audit, change)?;
 
     let start = std::time::Instant::now();
-    handle!(wtxn.commit());
+    wtxn.commit()?;
     let duration = start.elapsed().as_micros();
 
     Ok(HttpResponse::Ok().body(format!(
@@ -104,41 +139,326 @@ pub async fn create_processing_state(
 pub async fn create_payment(
     db_handles: web::Data<DBdata>,
     db_env: web::Data<DbEnv>,
+    audit: AuditContext,
     path: web::Path<String>,
     data: web::Json<Payments>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
-    let mut wtxn = handle!(db_env.env.write_txn());
+) -> Result<impl Responder, ApiError> {
+    let mut wtxn = db_env.env.write_txn()?;
     let permit_number = path.into_inner();
     let key = format!("{permit_number}-{:?}", data.date);
 
+    validate_payment(&data)?;
+    let previous = db_handles.db_data.payments_db.get(&wtxn, &key)?;
+
     let payment_data = Payments {
         date: data.date,
         payment: data.payment.to_owned(),
         amount: data.amount,
         status: data.status.to_owned(),
+        revision: previous.as_ref().map_or(1, |previous| previous.revision + 1),
     };
 
-    handle!(
-        db_handles
-            .db_data
-            .payments_db
-            .put(&mut wtxn, &key, &payment_data)
-    );
+    db_handles.db_data.payments_db.put(&mut wtxn, &key, &payment_data)?;
+
+    let change = match &previous {
+        Some(previous) => Change::updated(PAYMENTS_DB, &key, previous, &payment_data),
+        None => Change::created(PAYMENTS_DB, &key, &payment_data),
+    };
+    record_change(&db_handles.db_data, &mut wtxn, &audit, change)?;
 
     let start = std::time::Instant::now();
-    handle!(wtxn.commit());
+    wtxn.commit()?;
     let duration = start.elapsed().as_micros();
 
     Ok(HttpResponse::Ok().body(format!("Successfully added payment data for permit_numer: {permit_number}\nResponse Time: {duration}")))
 }
 
+#[post("/bulk/records")]
+pub async fn bulk_create_records(
+    db_env: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    audit: AuditContext,
+    options: web::Query<BulkOptions>,
+    body: web::Bytes,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let mode = options.mode.unwrap_or_default();
+
+    let items = match parse_bulk_items(&body) {
+        Ok(items) if !items.is_empty() => items,
+        Ok(_) => return Err(ApiError::validation("body", "No records in the request body")),
+        Err(message) => return Err(ApiError::validation("body", message)),
+    };
+
+    let mut wtxn = db_env.env.write_txn()?;
+    let mut results = vec![];
+    let mut accepted = vec![];
+    let mut batch_permits: HashMap<String, usize> = HashMap::new();
+
+    for (index, item) in items.into_iter().enumerate() {
+        let item = match item {
+            Ok(item) => item,
+            Err(message) => {
+                let error = ApiError::validation("body", message);
+                results.push(json!({ "index": index, "status": "invalid", "error": error.body() }));
+                continue;
+            }
+        };
+
+        let permit_number = &item.record.permit_number;
+        if permit_number.trim().is_empty() {
+            let error = ApiError::validation("permit_number", "permit_number must not be empty");
+            results.push(json!({ "index": index, "status": "invalid", "error": error.body() }));
+            continue;
+        }
+        if let Some(existing_key) = permit_number_owner(&db_handles.db_data, &wtxn, permit_number, None)? {
+            let error = ApiError::permit_conflict(permit_number, &existing_key);
+            results.push(json!({ "index": index, "status": "conflict", "error": error.body() }));
+            continue;
+        }
+        if let Some(first) = batch_permits.get(permit_number) {
+            let error = ApiError::conflict(
+                "permit_number",
+                format!("permit number '{permit_number}' is already used by item {first}"),
+            );
+            results.push(json!({ "index": index, "status": "conflict", "error": error.body() }));
+            continue;
+        }
+
+        let invalid_stage = item
+            .processing_states
+            .iter()
+            .find_map(|state| validate_stage(&wtxn, &db_handles.db_data, Some(&item.record.county), &state.processing_status).err());
+        if let Some(error) = invalid_stage {
+            results.push(json!({ "index": index, "status": "invalid", "error": error.body() }));
+            continue;
+        }
+
+        // Payments are keyed by date, so two on the same date would be one.
+        let mut payment_dates = HashSet::new();
+        let invalid_payment = item.payments.iter().find_map(|payment| match validate_payment(payment) {
+            Err(error) => Some(ApiError::from(error)),
+            Ok(()) if !payment_dates.insert(payment.date) => Some(ApiError::validation(
+                "payments",
+                format!("More than one payment is dated {:?}", payment.date),
+            )),
+            Ok(()) => None,
+        });
+        if let Some(error) = invalid_payment {
+            results.push(json!({ "index": index, "status": "invalid", "error": error.body() }));
+            continue;
+        }
+
+        batch_permits.insert(permit_number.to_owned(), index);
+        results.push(json!({ "index": index, "status": "valid" }));
+        accepted.push((index, item));
+    }
+
+    let failed = results.len() - accepted.len();
+    if mode == BulkMode::Atomic && failed > 0 {
+        for result in results.iter_mut().filter(|result| result["status"] == "valid") {
+            result["status"] = json!("not_written");
+        }
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "Response Time": start.elapsed().as_micros(),
+            "mode": mode,
+            "created": 0,
+            "failed": failed,
+            "Data": results
+        })));
+    }
+
+    let mut records = vec![];
+    for (index, item) in accepted {
+        let BulkRecord {
+            record,
+            processing_states,
+            payments,
+        } = item;
+        let (key, record) = insert_new_record(&db_handles.db_data, &mut wtxn, &audit, record)?;
+
+        let processing_state_count = processing_states.len();
+        unindex_current_state(&db_handles.db_data, &mut wtxn, &record.permit_number)?;
+        for mut processing_state in processing_states {
+            let state_key = format!("{}-{:?}", record.permit_number, processing_state.last_modified);
+            let previous = db_handles.db_data.processing_state.get(&wtxn, &state_key)?;
+            processing_state.revision = previous.as_ref().map_or(1, |previous| previous.revision + 1);
+
+            if let Some(previous) = &previous {
+                unindex_assignee(&db_handles.db_data, &mut wtxn, &state_key, previous)?;
+            }
+            db_handles.db_data.processing_state.put(&mut wtxn, &state_key, &processing_state)?;
+            index_assignee(&db_handles.db_data, &mut wtxn, &state_key, &processing_state)?;
+            let change = match &previous {
+                Some(previous) => Change::updated(PROCESSING_STATE_DB, &state_key, previous, &processing_state),
+                None => Change::created(PROCESSING_STATE_DB, &state_key, &processing_state),
+            };
+            record_change(&db_handles.db_data, &mut wtxn, &audit, change)?;
+        }
+        index_current_state(&db_handles.db_data, &mut wtxn, &record.permit_number)?;
+
+        let payment_count = payments.len();
+        for mut payment in payments {
+            let payment_key = format!("{}-{:?}", record.permit_number, payment.date);
+            let previous = db_handles.db_data.payments_db.get(&wtxn, &payment_key)?;
+            payment.revision = previous.as_ref().map_or(1, |previous| previous.revision + 1);
+
+            db_handles.db_data.payments_db.put(&mut wtxn, &payment_key, &payment)?;
+            let change = match &previous {
+                Some(previous) => Change::updated(PAYMENTS_DB, &payment_key, previous, &payment),
+                None => Change::created(PAYMENTS_DB, &payment_key, &payment),
+            };
+            record_change(&db_handles.db_data, &mut wtxn, &audit, change)?;
+        }
+
+        results[index] = json!({
+            "index": index,
+            "status": "created",
+            "key": key,
+            "processing_states": processing_state_count,
+            "payments": payment_count
+        });
+        records.push((key, record));
+    }
+
+    index_records(&db_handles.db_data, &mut wtxn, &records)?;
+    wtxn.commit()?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "Response Time": start.elapsed().as_micros(),
+        "mode": mode,
+        "created": records.len(),
+        "failed": failed,
+        "Data": results
+    })))
+}
+
+#[post("/import/records")]
+pub async fn import_records(
+    db_env: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    audit: AuditContext,
+    params: web::Query<HashMap<String, String>>,
+    body: web::Bytes,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let dry_run = match params.get("dry_run") {
+        Some(flag) => flag.trim().parse().map_err(|_| {
+            ApiError::validation("dry_run", format!("'{flag}' is not a boolean, expected true or false"))
+        })?,
+        None => false,
+    };
+
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(&body[..]);
+    let headers = reader
+        .headers()
+        .map_err(|e| ApiError::validation("body", format!("Could not read the CSV header: {e}")))?
+        .clone();
+    let mapping = ColumnMapping::new(&headers, &params)?;
+
+    // A dry run only reads, so it doesn't hold the writer lock while it
+    // checks the file.
+    let mut records = vec![];
+    let (rows, errors) = if dry_run {
+        let rtxn = db_env.env.read_txn()?;
+        let (rows, _, errors) = check_import_rows(&rtxn, &db_handles, reader, &mapping)?;
+        (rows, errors)
+    } else {
+        let mut wtxn = db_env.env.write_txn()?;
+        let (rows, valid, errors) = check_import_rows(&wtxn, &db_handles, reader, &mapping)?;
+        for record in valid {
+            records.push(insert_new_record(&db_handles.db_data, &mut wtxn, &audit, record)?);
+        }
+        index_records(&db_handles.db_data, &mut wtxn, &records)?;
+        wtxn.commit()?;
+        (rows, errors)
+    };
+
+    let valid = rows - errors.len();
+    Ok(HttpResponse::Ok().json(json!({
+        "Response Time": start.elapsed().as_micros(),
+        "dry_run": dry_run,
+        "rows": rows,
+        "valid": valid,
+        "created": records.len(),
+        "failed": errors.len(),
+        "ignored_columns": mapping.ignored,
+        "Errors": errors
+    })))
+}
+
+/// Parses every CSV row and checks its permit number against the database
+/// and the rows before it. Returns the row count, the rows that can be
+/// imported and the errors of the rest.
+fn check_import_rows(
+    rtxn: &RoTxn,
+    db_handles: &DBdata,
+    mut reader: csv::Reader<&[u8]>,
+    mapping: &ColumnMapping,
+) -> Result<(usize, Vec<DBSchema>, Vec<serde_json::Value>), ApiError> {
+    let mut errors = vec![];
+    let mut rows = 0;
+    let mut file_permits: HashMap<String, u64> = HashMap::new();
+    let mut valid = vec![];
+
+    for row in reader.records() {
+        rows += 1;
+        let (line, record) = match row {
+            Ok(row) => (
+                row.position().map_or(0, |position| position.line()),
+                mapping.parse_row(&row),
+            ),
+            Err(e) => {
+                let line = e.position().map_or(0, |position| position.line());
+                let error = ApiError::validation("body", e.to_string());
+                errors.push(json!({ "row": line, "status": "invalid", "errors": [error.body()] }));
+                continue;
+            }
+        };
+
+        let record = match record {
+            Ok(record) => record,
+            Err(row_errors) => {
+                errors.push(json!({ "row": line, "status": "invalid", "errors": row_errors }));
+                continue;
+            }
+        };
+
+        if let Some(existing_key) = permit_number_owner(
+            &db_handles.db_data,
+            rtxn,
+            &record.permit_number,
+            None
+        )? {
+            errors.push(json!({
+                "row": line,
+                "status": "conflict",
+                "errors": [ApiError::permit_conflict(&record.permit_number, &existing_key).body()]
+            }));
+            continue;
+        }
+        if let Some(first_line) = file_permits.get(&record.permit_number) {
+            let error = ApiError::conflict(
+                "permit_number",
+                format!("permit number '{}' is already used on row {first_line}", record.permit_number),
+            );
+            errors.push(json!({ "row": line, "status": "conflict", "errors": [error.body()] }));
+            continue;
+        }
+        file_permits.insert(record.permit_number.to_owned(), line);
+        valid.push(record);
+    }
+
+    Ok((rows, valid, errors))
+}
+
 #[get("/read-payment-details/{permit_number}")]
 pub async fn read_payment_details(
     db_handles: web::Data<DBdata>,
     db_env: web::Data<DbEnv>,
     path: web::Path<String>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
-    let rtxn = handle!(db_env.env.read_txn());
+) -> Result<impl Responder, ApiError> {
+    let rtxn = db_env.env.read_txn()?;
     let permit_numbers = path.into_inner();
     let permit_numbers: Vec<&str> = permit_numbers.split(",").collect();
     let mut final_result = vec![];
@@ -158,26 +478,52 @@ pub async fn read_payment_details(
     }
 
     let duration = start.elapsed().as_micros();
+    let mut builder = HttpResponse::Ok();
+    if let Some(tag) = single_row_etag(&final_result, |payment| payment.revision) {
+        builder.insert_header(tag);
+    }
     let response = json!({
         "Response Time": duration,
         "Data": final_result
     });
 
-    Ok(HttpResponse::Ok().json(response))
+    Ok(builder.json(response))
 }
 
+/// Processing states of one or more comma separated permits, in key order.
+/// With `current=true`, only the current state of each.
 #[get("/read-processing-status/{permit_number}")]
 pub async fn read_processing_state(
     db_handles: web::Data<DBdata>,
     db_env: web::Data<DbEnv>,
     path: web::Path<String>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
+    options: web::Query<ProcessingReadOptions>,
+) -> Result<impl Responder, ApiError> {
     let start = std::time::Instant::now();
-    let rtxn = handle!(db_env.env.read_txn());
+    let rtxn = db_env.env.read_txn()?;
     let keys = path.into_inner();
     let keys: Vec<&str> = keys.split(",").collect();
     let mut final_result = vec![];
 
+    if options.current == Some(true) {
+        let mut current_states = vec![];
+        for permit_number in keys {
+            let current = current_processing_state(&db_handles.db_data, &rtxn, permit_number)?;
+            current_states.push(current.into_iter().collect::<Vec<_>>());
+        }
+
+        let duration = start.elapsed().as_micros();
+        let mut builder = HttpResponse::Ok();
+        if let Some(tag) = single_row_etag(&current_states, |state| state.revision) {
+            builder.insert_header(tag);
+        }
+        let response = json!({
+            "Response Time": duration,
+            "Data": current_states
+        });
+        return Ok(builder.json(response));
+    }
+
     for key in keys {
         let key = format!("{key}-");
         let db = db_handles.db_data.processing_state;
@@ -192,788 +538,875 @@ pub async fn read_processing_state(
     }
 
     let duration = start.elapsed().as_micros();
+    let mut builder = HttpResponse::Ok();
+    if let Some(tag) = single_row_etag(&final_result, |state| state.revision) {
+        builder.insert_header(tag);
+    }
     let response = json!({
         "Response Time": duration,
         "Data": final_result
     });
 
+    Ok(builder.json(response))
+}
+
+/// Permits whose latest processing state is past its due date, grouped by
+/// assignee, most overdue first.
+#[get("/processing/overdue")]
+pub async fn read_overdue_processing(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
+    let now = chrono::Utc::now().naive_utc();
+
+    let overdue = due_states(&rtxn, &db_handles.db_data, None, now)?;
+    let count = overdue.len();
+
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "as_of": now,
+        "Number_of_permits": count,
+        "Data": group_by_assignee(overdue, now)
+    });
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Permits whose latest processing state falls due between now and
+/// `within` (default `7d`) from now, grouped by assignee.
+#[get("/processing/due")]
+pub async fn read_due_processing(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+    query: web::Query<DueQuery>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
+    let now = chrono::Utc::now().naive_utc();
+
+    let within = query.within.as_deref().unwrap_or("7d");
+    let window = parse_within(within).ok_or_else(|| {
+        ApiError::validation("within", format!("'{within}' is not a valid window, expected a number of hours, days or weeks such as 12h, 7d or 2w"))
+    })?;
+    let until = now.checked_add_signed(window).ok_or_else(|| ApiError::validation("within", format!("'{within}' is too far ahead")))?;
+
+    let due = due_states(&rtxn, &db_handles.db_data, Some(now), until)?;
+    let count = due.len();
+
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "as_of": now,
+        "until": until,
+        "Number_of_permits": count,
+        "Data": group_by_assignee(due, now)
+    });
+
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// Everything whose latest processing state is assigned to `name`, one
+/// entry per permit with a summary of its record, ordered by `due_date`.
+#[get("/assignees/{name}/queue")]
+pub async fn read_assignee_queue(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+    path: web::Path<String>,
+    query: web::Query<QueueQuery>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
+    let assignee = path.into_inner();
+
+    let descending = parse_queue_sort(query.sort.as_deref())?;
+    let queue = assignee_queue(&rtxn, &db_handles.db_data, &assignee, descending)?;
+
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "assignee": assignee,
+        "Number_of_permits": queue.len(),
+        "Data": queue.iter().map(|item| item.to_json()).collect::<Vec<_>>()
+    });
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Every county with its own workflow stages, plus the built-in stages the
+/// other counties use.
+#[get("/workflow-stages")]
+pub async fn read_workflow_stages(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
+
+    let mut counties = serde_json::Map::new();
+    for entry in db_handles.db_data.workflow_stages.iter(&rtxn)? {
+        let (county, stages) = entry?;
+        counties.insert(county.to_string(), json!(stages));
+    }
+
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "default": ProcessStatus::BUILT_IN,
+        "Data": counties
+    });
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[get("/workflow-stages/{county}")]
+pub async fn read_county_workflow_stages(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+    path: web::Path<String>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
+    let county = path.into_inner();
+
+    let configured = db_handles.db_data.workflow_stages.get(&rtxn, &county)?.is_some();
+    let stages = county_stages(&rtxn, &db_handles.db_data, &county)?;
+
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "county": county,
+        "configured": configured,
+        "stages": stages
+    });
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Sets a county's workflow stages, in pipeline order. Processing states
+/// already stored keep whatever stage they have.
+#[put("/workflow-stages/{county}")]
+pub async fn update_workflow_stages(
+    db_env: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    audit: AuditContext,
+    path: web::Path<String>,
+    names: web::Json<Vec<String>>,
+) -> Result<impl Responder, ApiError> {
+    let mut wtxn = db_env.env.write_txn()?;
+    let county = path.into_inner();
+
+    let stages = parse_stages(&names).map_err(|message| ApiError::validation("stages", message))?;
+    let previous = db_handles.db_data.workflow_stages.get(&wtxn, &county)?;
+    db_handles.db_data.workflow_stages.put(&mut wtxn, &county, &stages)?;
+
+    let after = json!({ "stages": stages });
+    let before = previous.map(|previous| json!({ "stages": previous }));
+    let change = match &before {
+        Some(before) => Change::updated(WORKFLOW_STAGES_DB, &county, before, &after),
+        None => Change::created(WORKFLOW_STAGES_DB, &county, &after),
+    };
+    record_change(&db_handles.db_data, &mut wtxn, &audit, change)?;
+
+    let start = std::time::Instant::now();
+    wtxn.commit()?;
+    let duration = start.elapsed().as_micros();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "Response Time": duration,
+        "county": county,
+        "stages": stages
+    })))
+}
+
+/// Puts a county back on the built-in stages.
+#[delete("/workflow-stages/{county}")]
+pub async fn delete_workflow_stages(
+    db_env: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    audit: AuditContext,
+    path: web::Path<String>,
+) -> Result<impl Responder, ApiError> {
+    let mut wtxn = db_env.env.write_txn()?;
+    let county = path.into_inner();
+
+    let Some(previous) = db_handles.db_data.workflow_stages.get(&wtxn, &county)? else {
+        return Err(ApiError::not_found(format!("County {county} has no workflow stages of its own")));
+    };
+    db_handles.db_data.workflow_stages.delete(&mut wtxn, &county)?;
+    let before = json!({ "stages": previous });
+    record_change(
+        &db_handles.db_data,
+        &mut wtxn,
+        &audit,
+        Change::deleted(WORKFLOW_STAGES_DB, &county, &before)
+    )?;
+
+    let start = std::time::Instant::now();
+    wtxn.commit()?;
+    let duration = start.elapsed().as_micros();
+
+    Ok(HttpResponse::Ok().body(format!(
+        "County {county} is back on the built-in workflow stages\nResponse Time: {duration}"
+    )))
+}
+
 #[get("/read-record-by-uuid/{key}")]
 pub async fn read_record_by_uuid(
     db: web::Data<DbEnv>,
     db_handles: web::Data<DBdata>,
     path: web::Path<String>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
+    options: web::Query<ReadOptions>,
+) -> Result<impl Responder, ApiError> {
     let start = std::time::Instant::now();
 
     let key = path.into_inner();
-    let rtxn = db.env.read_txn().unwrap();
+    let rtxn = db.env.read_txn()?;
 
     let main_db = db_handles.db_data.main_db;
 
-    let record = {
-        match main_db.get(&rtxn, &key) {
-            Ok(Some(record)) => Some(record),
-            Ok(_) => {
-                return Ok(HttpResponse::NotFound()
-                    .body(format!("No Record found with the uuid: {}", key)));
-            }
-            Err(e) => {
-                eprintln!("Database error: {}", e);
-                return Ok(HttpResponse::InternalServerError().body("Database Error"));
-            }
-        }
+    let not_found = || ApiError::not_found(format!("No Record found with the uuid: {}", key));
+    let (record, deleted_at) = match main_db.get(&rtxn, &key)? {
+        Some(record) => (record, None),
+        None if options.include_deleted == Some(true) => match db_handles.db_data.deleted_db.get(&rtxn, &key)? {
+            Some(tombstone) => (tombstone.record, Some(tombstone.deleted_at)),
+            None => return Err(not_found()),
+        },
+        None => return Err(not_found()),
     };
 
-    if let Some(record) = record {
-        let duration = start.elapsed();
-        return Ok(HttpResponse::Ok().body(format!(
-            "permit_link: {}\npermit_number: {}\nclient: {}\nopened_date: {}\nlast_updated: {}
-            \n status_updated: {}\n county: {}\n county_status: {}\n manual_status: {}\naddress: {}
-            \nResponse Time: {}",
-            record.permit_link,
-            record.permit_number,
-            record.client,
-            record.opened,
-            record.last_updated,
-            record.status_updated,
-            record.county,
-            record.county_status,
-            record.manual_status,
-            record.address,
-            duration.as_micros()
-        )));
-    }
-
-    Ok(HttpResponse::Ok().body("No record Found"))
+    let deleted = match deleted_at {
+        Some(deleted_at) => format!("\ndeleted_at: {deleted_at}"),
+        None => String::new(),
+    };
+    let duration = start.elapsed();
+    Ok(HttpResponse::Ok().insert_header(etag(record.revision)).body(format!(
+        "permit_link: {}\npermit_number: {}\nclient: {}\nopened_date: {}\nlast_updated: {}
+        \n status_updated: {}\n county: {}\n county_status: {}\n manual_status: {}\naddress: {}\nnormalized_address: {}\nrevision: {}{}
+        \nResponse Time: {}",
+        record.permit_link,
+        record.permit_number,
+        record.client,
+        record.opened,
+        record.last_updated,
+        record.status_updated,
+        record.county,
+        record.county_status,
+        record.manual_status,
+        record.address,
+        record.normalized_address,
+        record.revision,
+        deleted,
+        duration.as_micros()
+    )))
 }
 
-#[get("/read-records-by-opened-date")]
-pub async fn read_records_by_opened_date(
+/// Where a record's `county_status` and `manual_status` may move next under
+/// the transition table, plus every transition it has made so far.
+#[get("/permits/transitions/{uuid}")]
+pub async fn read_permit_transitions(
     db_handles: web::Data<DBdata>,
     db_env: web::Data<DbEnv>,
-    dates: web::Json<HashMap<String, String>>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
+    config: web::Data<AppConfig>,
+    path: web::Path<String>,
+) -> Result<impl Responder, ApiError> {
     let start = std::time::Instant::now();
-    let rtxn = handle!(db_env.env.read_txn());
-
-    if let Some(start_date) = dates.get("start_date") {
-        if let Some(end_date) = dates.get("end_date") {
-            let start_date = NaiveDate::parse_from_str(&start_date, "%Y-%m-%d")
-                .expect("Invalid start_date format, expected YYYY-MM-DD");
-            let end_date = NaiveDate::parse_from_str(&end_date, "%Y-%m-%d")
-                .expect("Invalid end_date format, expected YYYY-MM-DD");
-            let mut dates = vec![];
-            let mut current_date = start_date;
-            let mut records = vec![];
-
-            while current_date <= end_date {
-                dates.push(current_date.format("%Y-%m-%d").to_string());
-                current_date += chrono::Duration::days(1);
-            }
+    let rtxn = db_env.env.read_txn()?;
+    let uuid = path.into_inner();
 
-            for date in dates {
-                let mut cursor = db_handles.db_data.main_db.prefix_iter(&rtxn, &date)?;
+    let Some(record) = db_handles.db_data.main_db.get(&rtxn, &uuid)? else {
+        return Err(ApiError::not_found(format!("No Record found with the uuid: {uuid}")));
+    };
+    let history = db_handles.db_data.status_history.get(&rtxn, &uuid)?.unwrap_or_default();
+    let now = chrono::Utc::now().naive_utc();
 
-                while let Some(Ok((key, value))) = cursor.next() {
-                    records.push((key, value))
-                }
-            }
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "key": uuid,
+        "county_status": config.transitions.next_states_json(&record.county_status, record.status_updated, now),
+        "manual_status": config.transitions.next_states_json(&record.manual_status, record.status_updated, now),
+        "history": history
+    });
 
-            let duration = start.elapsed().as_micros();
+    Ok(HttpResponse::Ok().insert_header(etag(record.revision)).json(response))
+}
 
-            let response = json!({
-                "Response Time": duration,
-                "Data": records
-            });
+/// Records whose normalized address is within `min_score` (default 0.8) of
+/// the given record's, closest first. Scores are 1 minus the edit distance
+/// over the length of the longer address.
+#[get("/permits/similar/{uuid}")]
+pub async fn read_similar_permits(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+    path: web::Path<String>,
+    query: web::Query<SimilarQuery>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
+    let uuid = path.into_inner();
 
-            return Ok(HttpResponse::Ok().json(response));
+    let min_score = query.min_score.unwrap_or(0.8);
+    if !(0.0..=1.0).contains(&min_score) {
+        return Err(ApiError::validation("min_score", "min_score must be between 0 and 1"));
+    }
+
+    let Some(record) = db_handles.db_data.main_db.get(&rtxn, &uuid)? else {
+        return Err(ApiError::not_found(format!("No Record found with the uuid: {uuid}")));
+    };
+    let target = &record.normalized_address;
+    let target_length = target.chars().count();
+
+    let mut matches = vec![];
+    for entry in db_handles.db_data.main_db.iter(&rtxn)? {
+        let (key, candidate) = entry?;
+        if key == uuid {
+            continue;
+        }
+
+        // The length difference is a lower bound on the edit distance, so
+        // most candidates are ruled out without computing it.
+        let candidate_length = candidate.normalized_address.chars().count();
+        let longest = target_length.max(candidate_length).max(1);
+        if 1.0 - (target_length.abs_diff(candidate_length) as f64 / longest as f64) < min_score {
+            continue;
+        }
+
+        let (distance, score) = similarity(target, &candidate.normalized_address);
+        if score >= min_score {
+            matches.push((distance, score, key.to_string(), candidate));
         }
     }
+    matches.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.cmp(&b.2)));
+    matches.truncate(query.limit.unwrap_or(DEFAULT_PAGE_SIZE));
 
-    Ok(HttpResponse::Ok().body("Both start_date and end_date must exist"))
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "key": uuid,
+        "normalized_address": target,
+        "Number_of_matches": matches.len(),
+        "Data": matches
+            .into_iter()
+            .map(|(distance, score, key, candidate)| json!({
+                "key": key,
+                "distance": distance,
+                "score": score,
+                "permit_number": candidate.permit_number,
+                "address": candidate.address,
+                "normalized_address": candidate.normalized_address
+            }))
+            .collect::<Vec<_>>()
+    });
+
+    Ok(HttpResponse::Ok().json(response))
 }
 
-#[get("/read-permits-with-filter")]
-pub async fn read_permit_with_filter(
+#[get("/stats/permits")]
+pub async fn read_permit_stats(
     db_handles: web::Data<DBdata>,
     db_env: web::Data<DbEnv>,
-    filter_data: Option<web::Json<HashMap<String, String>>>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
+    query: web::Query<StatsQuery>,
+) -> Result<impl Responder, ApiError> {
     let start = std::time::Instant::now();
-    let rtxn = handle!(db_env.env.read_txn());
-
-    if let Some(filter_data) = filter_data {
-        if let Some(start_date) = filter_data.get("start_date") {
-            if let Some(end_date) = filter_data.get("end_date") {
-                let start_date = NaiveDate::parse_from_str(&start_date, "%Y-%m-%d")
-                    .expect("Invalid start_date format, expected YYYY-MM-DD");
-                let end_date = NaiveDate::parse_from_str(&end_date, "%Y-%m-%d")
-                    .expect("Invalid end_date format, expected YYYY-MM-DD");
-                let mut dates = vec![];
-                let mut current_date = start_date;
-                let mut records = vec![];
-
-                while current_date <= end_date {
-                    dates.push(current_date.format("%Y-%m-%d").to_string());
-                    current_date += chrono::Duration::days(1);
-                }
+    let rtxn = db_env.env.read_txn()?;
 
-                for date in dates {
-                    let mut cursor = db_handles.db_data.main_db.prefix_iter(&rtxn, &date)?;
+    let group_by = GroupBy::parse_list(query.group_by.as_deref())?;
+    let filter = RecordFilter::parse(&query.filters, &[])?;
+    let stats = count_permits(&rtxn, &db_handles.db_data, &filter, &group_by)?;
 
-                    while let Some(Ok((key, value))) = cursor.next() {
-                        records.push((key, value))
-                    }
-                }
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "group_by": group_by.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        "source": stats.source,
+        "total": stats.total(),
+        "Data": stats.rows(&group_by)
+    });
 
-                let county = match filter_data.get("county") {
-                    Some(county) => county,
-                    None => "",
-                };
-                let county_status = match filter_data.get("county_status") {
-                    Some(county_status) => county_status,
-                    None => "",
-                };
-                let client = match filter_data.get("client") {
-                    Some(client) => client,
-                    None => "",
-                };
+    Ok(HttpResponse::Ok().json(response))
+}
 
-                if county.is_empty() && county_status.is_empty() && client.is_empty() {
-                    let duration = start.elapsed().as_micros();
-                    let response = json!({
-                        "Response Time": duration,
-                        "Data": records
-                    });
+#[get("/stats/counters/check")]
+pub async fn check_stats_counters(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
+    let drift = check_counters(&rtxn, &db_handles.db_data)?;
 
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "consistent": drift.is_empty(),
+        "Number_of_drifted": drift.len(),
+        "Data": drift
+    });
 
-                if !county.is_empty() && county_status.is_empty() && client.is_empty() {
-                    let mut final_results = vec![];
-                    for item in records {
-                        if item.1.county == county {
-                            final_results.push((item.0, item.1))
-                        }
-                    }
-
-                    let duration = start.elapsed().as_micros();
-                    let response = json!({
-                        "Response Time": duration,
-                        "Data": final_results
-                    });
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+    Ok(HttpResponse::Ok().json(response))
+}
 
-                if county.is_empty() && !county_status.is_empty() && client.is_empty() {
-                    let mut final_results = vec![];
-                    for item in records {
-                        if item.1.county_status.to_string() == county_status {
-                            final_results.push((item.0, item.1))
-                        }
-                    }
-
-                    let duration = start.elapsed().as_micros();
-                    let response = json!({
-                        "Response Time": duration,
-                        "Data": final_results
-                    });
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+#[get("/permits/duplicates")]
+pub async fn read_duplicate_permits(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
+    let mut keys_by_permit: HashMap<String, Vec<String>> = HashMap::new();
+
+    for entry in db_handles.db_data.main_db.iter(&rtxn)? {
+        let (key, record) = entry?;
+        keys_by_permit
+            .entry(record.permit_number)
+            .or_default()
+            .push(key.to_string());
+    }
 
-                if county.is_empty() && county_status.is_empty() && !client.is_empty() {
-                    let mut final_results = vec![];
-                    for item in records {
-                        if item.1.client == client {
-                            final_results.push((item.0, item.1))
-                        }
-                    }
-
-                    let duration = start.elapsed().as_micros();
-                    let response = json!({
-                        "Response Time": duration,
-                        "Data": final_results
-                    });
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+    let mut duplicates: Vec<(String, Vec<String>)> = keys_by_permit
+        .into_iter()
+        .filter(|(_, keys)| keys.len() > 1)
+        .collect();
+    duplicates.sort();
 
-                if !county.is_empty() && !county_status.is_empty() && client.is_empty() {
-                    let mut final_results = vec![];
-                    for item in records {
-                        if item.1.county == county
-                            && item.1.county_status.to_string() == county_status
-                        {
-                            final_results.push((item.0, item.1))
-                        }
-                    }
-
-                    let duration = start.elapsed().as_micros();
-                    let response = json!({
-                        "Response Time": duration,
-                        "Data": final_results
-                    });
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "Number_of_duplicates": duplicates.len(),
+        "Data": duplicates
+            .iter()
+            .map(|(permit_number, keys)| json!({ "permit_number": permit_number, "keys": keys }))
+            .collect::<Vec<_>>()
+    });
 
-                if county.is_empty() && !county_status.is_empty() && !client.is_empty() {
-                    let mut final_results = vec![];
-                    for item in records {
-                        if item.1.client == client
-                            && item.1.county_status.to_string() == county_status
-                        {
-                            final_results.push((item.0, item.1))
-                        }
-                    }
-
-                    let duration = start.elapsed().as_micros();
-                    let response = json!({
-                        "Response Time": duration,
-                        "Data": final_results
-                    });
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+    Ok(HttpResponse::Ok().json(response))
+}
 
-                if !county.is_empty() && county_status.is_empty() && !client.is_empty() {
-                    let mut final_results = vec![];
-                    for item in records {
-                        if item.1.county == county && item.1.client == client {
-                            final_results.push((item.0, item.1))
-                        }
-                    }
-
-                    let duration = start.elapsed().as_micros();
-                    let response = json!({
-                        "Response Time": duration,
-                        "Data": final_results
-                    });
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+#[get("/permits/{permit_number}")]
+pub async fn read_permit(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+    path: web::Path<String>,
+    options: web::Query<ReadOptions>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
+    let permit_number = path.into_inner();
 
-                if !county.is_empty() && !county_status.is_empty() && !client.is_empty() {
-                    let mut final_results = vec![];
-                    for item in records {
-                        if item.1.county == county
-                            && item.1.county_status.to_string() == county_status
-                            && item.1.client == client
-                        {
-                            final_results.push((item.0, item.1))
-                        }
-                    }
-
-                    let duration = start.elapsed().as_micros();
-                    let response = json!({
-                        "Response Time": duration,
-                        "Data": final_results
-                    });
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+    let indexed_key = db_handles.db_data.permit_index.get(&rtxn, &permit_number)?
+        .map(|key| key.to_string());
 
-                return Ok(HttpResponse::Ok().body("No Records Found"))
-            }
+    let mut found = None;
+    if let Some(key) = indexed_key
+        && let Some(record) = db_handles.db_data.main_db.get(&rtxn, &key)?
+    {
+        found = Some((key, record, None));
+    }
+
+    if found.is_none()
+        && options.include_deleted == Some(true)
+        && let Some(key) = db_handles.db_data.deleted_permit_index.get(&rtxn, &permit_number)?
+        && let Some(tombstone) = db_handles.db_data.deleted_db.get(&rtxn, key)?
+    {
+        found = Some((key.to_string(), tombstone.record, Some(tombstone.deleted_at)));
+    }
+
+    let Some((key, record, deleted_at)) = found else {
+        return Err(ApiError::not_found(format!(
+            "No Record found with the permit number: {permit_number}"
+        )));
+    };
+
+    let prefix = format!("{permit_number}-");
+    let mut processing_history = vec![];
+    for entry in db_handles.db_data.processing_state.prefix_iter(&rtxn, &prefix)? {
+        let (child_key, value) = entry?;
+        if is_child_key(child_key, &permit_number) {
+            processing_history.push((child_key, value));
         }
     }
 
-    Ok(HttpResponse::Ok().body("Both start_date and end_date must exist"))
+    let mut payments = vec![];
+    for entry in db_handles.db_data.payments_db.prefix_iter(&rtxn, &prefix)? {
+        let (child_key, value) = entry?;
+        if is_child_key(child_key, &permit_number) {
+            payments.push((child_key, value));
+        }
+    }
+
+    let duration = start.elapsed().as_micros();
+    let revision = record.revision;
+    let response = json!({
+        "Response Time": duration,
+        "key": key,
+        "record": record,
+        "deleted_at": deleted_at,
+        "processing_history": processing_history,
+        "payments": payments
+    });
+
+    Ok(HttpResponse::Ok().insert_header(etag(revision)).json(response))
 }
 
-#[get("/read-record")]
-pub async fn read_record(
-    db: web::Data<DbEnv>,
+#[get("/read-records-by-opened-date")]
+pub async fn read_records_by_opened_date(
     db_handles: web::Data<DBdata>,
-    query: Option<web::Json<HashMap<String, String>>>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
+    db_env: web::Data<DbEnv>,
+    dates: ListingParams<DateRangeQuery>,
+) -> Result<impl Responder, ApiError> {
     let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
 
-    let rtxn = handle!(db.env.read_txn());
-    let main_db = db_handles.db_data.main_db;
-    let cursor = handle!(main_db.iter(&rtxn));
-    let stats = handle!(main_db.stat(&rtxn));
-    let entries = stats.entries;
-
-    if let Some(query) = query {
-        if query.is_empty() {
-            let records: Vec<(String, DBSchema)> = cursor
-                .take(50)
-                .filter_map(|res| res.ok().map(|(key, value)| (key.to_string(), value)))
-                .collect();
+    let date_params = dates.params.filter_params();
+    require_date_range(&date_params)?;
+    let filter = RecordFilter::parse(&date_params, &[])?;
+    let order = SortOrder::parse(dates.params.sort.as_deref(), None)?;
+    let params = &dates.params;
+    let request = PageRequest::on_request(params.cursor.as_deref(), params.page, params.records_per_page);
+    let page = page_of_matches(&rtxn, &db_handles.db_data, &filter, &order, &request)?;
 
-            let duration = start.elapsed();
-            let response = data_with_response_time(duration, records, entries);
+    let duration = start.elapsed().as_micros();
+    let mut response = json!({
+        "Response Time": duration,
+        "Data": page.records,
+        "next_cursor": page.next_cursor
+    });
+    if dates.params.include_deleted == Some(true) {
+        let deleted_records = collect_deleted_matches(&rtxn, &db_handles.db_data, &filter)?;
+        response["Deleted Data"] = json!(deleted_records);
+    }
 
-            return Ok(HttpResponse::Ok().json(response));
-        }
+    Ok(dates.ok().json(response))
+}
 
-        let page = match query.get("page") {
-            Some(page) => page,
-            None => "",
-        };
-        let county = match query.get("county") {
-            Some(county) => county,
-            None => "",
-        };
-        let client = match query.get("client") {
-            Some(client) => client,
-            None => "",
-        };
-        let status = match query.get("county_status") {
-            Some(status) => status,
-            None => "",
-        };
-        let pagination = match query.get("records_per_page") {
-            Some(pagination) => pagination,
-            None => "",
-        };
-        let sort = match query.get("sort") {
-            Some(sorted) => sorted,
-            None => "",
-        };
-        let sort_key = match query.get("sort_key") {
-            Some(sort_key) => sort_key,
-            None => "",
-        };
+#[get("/read-permits-with-filter")]
+pub async fn read_permit_with_filter(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+    filter_data: ListingParams<PermitFilterQuery>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
 
-        if county.is_empty() && client.is_empty() && status.is_empty() {
-            if !page.is_empty() {
-                let stats = handle!(main_db.stat(&rtxn));
-                let entires = stats.entries;
-                let mut page: usize = match page.parse() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        return Ok(HttpResponse::Ok()
-                            .body("Enter a valid page number. It must be an integer."));
-                    }
-                };
+    let filters = &filter_data.params.filters;
+    let mut filter = RecordFilter::parse(filters, &processing_status_params(filters))?;
+    filter.filters.extend(processing_status_filter(&rtxn, &db_handles.db_data, filters)?);
+    let order = SortOrder::parse(filter_data.params.sort.as_deref(), None)?;
+    let params = &filter_data.params;
+    let request = PageRequest::on_request(params.cursor.as_deref(), params.page, params.records_per_page);
+    let page = page_of_matches(&rtxn, &db_handles.db_data, &filter, &order, &request)?;
 
-                if pagination.is_empty() {
-                    let page_in_db = entires / 50;
-                    if sort.is_empty() || sort == "asc" {
-                        if entires < page * 50 {
-                            return Ok(HttpResponse::Ok()
-                                .body(format!("The DB only has {page_in_db} Pages")));
-                        }
-
-                        if page == 1 {
-                            page -= 1
-                        }
-
-                        let records: Vec<(String, DBSchema)> = cursor
-                            .skip(page * 50)
-                            .take(50)
-                            .filter_map(|res| res.ok().map(|(key, value)| (key.to_string(), value)))
-                            .collect();
-
-                        let duration = start.elapsed();
-                        let response = data_with_response_time(duration, records, entires);
-
-                        return Ok(HttpResponse::Ok().json(response));
-                    } else {
-                        if entires < page * 50 {
-                            return Ok(HttpResponse::Ok()
-                                .body(format!("The DB only has {page_in_db} Pages")));
-                        }
-
-                        page = page_in_db - page - 1;
-                        let records: Vec<(String, DBSchema)> = cursor
-                            .skip(page * 50)
-                            .take(50)
-                            .filter_map(|res| res.ok().map(|(key, value)| (key.to_string(), value)))
-                            .collect();
-
-                        let duration = start.elapsed();
-                        let response = data_with_response_time(duration, records, entires);
-
-                        return Ok(HttpResponse::Ok().json(response));
-                    }
-                } else if !pagination.is_empty() {
-                    let pagination = match pagination.parse() {
-                        Ok(num) => num,
-                        Err(_) => {
-                            return Ok(HttpResponse::Ok()
-                                .body("Enter a valid page number. It must be an integer."));
-                        }
-                    };
-                    let page_in_db = entires / pagination;
-
-                    if sort.is_empty() || sort == "asc" {
-                        if entires < page * pagination {
-                            return Ok(HttpResponse::Ok()
-                                .body(format!("The DB only has {page_in_db} Pages")));
-                        }
-
-                        if page == 1 {
-                            page -= 1
-                        }
-
-                        let records: Vec<(String, DBSchema)> = cursor
-                            .skip(page * pagination)
-                            .take(pagination)
-                            .filter_map(|res| res.ok().map(|(key, value)| (key.to_string(), value)))
-                            .collect();
-
-                        let duration = start.elapsed();
-                        let response = data_with_response_time(duration, records, entries);
-
-                        return Ok(HttpResponse::Ok().json(response));
-                    } else {
-                        if entires < page * pagination {
-                            return Ok(HttpResponse::Ok()
-                                .body(format!("The DB only has {page_in_db} Pages")));
-                        }
-
-                        page = page_in_db - page - 1;
-                        let records: Vec<(String, DBSchema)> = cursor
-                            .skip(page * pagination)
-                            .take(pagination)
-                            .filter_map(|res| res.ok().map(|(key, value)| (key.to_string(), value)))
-                            .collect();
-
-                        let duration = start.elapsed();
-                        let response = data_with_response_time(duration, records, entires);
-
-                        return Ok(HttpResponse::Ok().json(response));
-                    }
-                }
-            } else if page.is_empty() {
-                if !pagination.is_empty() {
-                    let stats = handle!(main_db.stat(&rtxn));
-                    let entires = stats.entries;
-                    let pagination = match pagination.parse() {
-                        Ok(num) => num,
-                        Err(_) => {
-                            return Ok(HttpResponse::Ok()
-                                .body("Enter a valid page number. It must be an integer."));
-                        }
-                    };
-
-                    let page_in_db = entires / pagination;
-                    if sort.is_empty() || sort == "asc" {
-                        let records: Vec<(String, DBSchema)> = cursor
-                            .take(pagination)
-                            .filter_map(|res| res.ok().map(|(key, value)| (key.to_string(), value)))
-                            .collect();
-
-                        let duration = start.elapsed();
-                        let response = data_with_response_time(duration, records, entires);
-
-                        return Ok(HttpResponse::Ok().json(response));
-                    } else {
-                        let records: Vec<(String, DBSchema)> = cursor
-                            .skip(page_in_db - pagination)
-                            .take(pagination)
-                            .filter_map(|res| res.ok().map(|(key, value)| (key.to_string(), value)))
-                            .collect();
-
-                        let duration = start.elapsed();
-                        let response = data_with_response_time(duration, records, entires);
-
-                        return Ok(HttpResponse::Ok().json(response));
-                    }
-                } else if pagination.is_empty() {
-                    let stats = handle!(main_db.stat(&rtxn));
-                    let entires = stats.entries;
-
-                    if sort.is_empty() || sort == "asc" {
-                        let records: Vec<(String, DBSchema)> = cursor
-                            .take(50)
-                            .filter_map(|res| res.ok().map(|(key, value)| (key.to_string(), value)))
-                            .collect();
-
-                        let duration = start.elapsed();
-                        let response = data_with_response_time(duration, records, entires);
-
-                        return Ok(HttpResponse::Ok().json(response));
-                    } else {
-                        let skip = entires - 50;
-                        let records: Vec<(String, DBSchema)> = cursor
-                            .skip(skip)
-                            .take(50)
-                            .filter_map(|res| res.ok().map(|(key, value)| (key.to_string(), value)))
-                            .collect();
-
-                        let duration = start.elapsed();
-                        let response = data_with_response_time(duration, records, entires);
-
-                        return Ok(HttpResponse::Ok().json(response));
-                    }
-                }
-            }
-        }
+    let duration = start.elapsed().as_micros();
+    let mut response = json!({
+        "Response Time": duration,
+        "Data": page.records,
+        "next_cursor": page.next_cursor
+    });
+    if filter_data.params.include_deleted == Some(true) {
+        let deleted_records = collect_deleted_matches(&rtxn, &db_handles.db_data, &filter)?;
+        response["Deleted Data"] = json!(deleted_records);
+    }
 
-        if !county.is_empty() && !client.is_empty() && !status.is_empty() {
-            let status = match Status::from_str(status) {
-                Ok(stat) => stat,
-                Err(_) => {
-                    return Ok(HttpResponse::Ok().body("The given status is not valid."));
-                }
-            };
-            let key = KeySchema {
-                county: county.to_string(),
-                client: client.to_string(),
-                county_status: status,
-            };
+    Ok(filter_data.ok().json(response))
+}
 
-            let set = handle!(db_handles.db_data.composite_index.get(&rtxn, &key));
+#[get("/read-record")]
+pub async fn read_record(
+    db: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    query: ListingParams<RecordListQuery>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db.env.read_txn()?;
+
+    let params = &query.params;
+    let mut filter = RecordFilter::parse(&params.filters, &processing_status_params(&params.filters))?;
+    filter.filters.extend(processing_status_filter(&rtxn, &db_handles.db_data, &params.filters)?);
+    let order = SortOrder::parse(params.sort.as_deref(), params.sort_key.as_deref())?;
+    let request = PageRequest {
+        cursor: params.cursor.as_deref(),
+        page: params.page,
+        limit: match params.records_per_page {
+            Some(limit) => Some(limit),
+            None if filter.is_empty() || params.cursor.is_some() || params.page.is_some() => Some(DEFAULT_PAGE_SIZE),
+            None => None,
+        },
+    };
 
-            if let Some(set) = set {
-                let mut records =
-                    match helper_function_for_retrieving_data(&set, &rtxn, &db_handles) {
-                        Ok(records) => records,
-                        Err(_) => return Ok(HttpResponse::Ok().body("Failed to retrieve records")),
-                    };
+    let response = listing_page(
+        &rtxn,
+        &db_handles,
+        &filter,
+        &order,
+        &request,
+        params.include_deleted == Some(true),
+        start,
+    )?;
+
+    Ok(query.ok().json(response))
+}
 
-                let pagination: usize = match pagination.parse() {
-                    Ok(pagination) => pagination,
-                    Err(_) => 0,
-                };
+/// Same filters, sorting and paging as `/read-record`, taken from a JSON body
+/// for clients whose filters do not fit comfortably in a URL.
+#[post("/search")]
+pub async fn search_records(
+    db_env: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    search: web::Json<SearchRequest>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
 
-                if (sort.is_empty() || sort == "asc") && sort_key.is_empty() {
-                    records.sort_by_key(|schema| schema.opened);
-
-                    if pagination != 0 {
-                        if let Some(slice) = records.get(..pagination) {
-                            let duration = start.elapsed();
-                            let response =
-                                data_with_response_time_for_slice(duration, slice, set.len());
-
-                            return Ok(HttpResponse::Ok().json(response));
-                        }
-                    }
-                    let duration = start.elapsed();
-                    let response = data_with_response_time_for_slice(duration, &records, set.len());
-
-                    return Ok(HttpResponse::Ok().json(response));
-                } else if sort == "dsc" && sort_key.is_empty() {
-                    records.sort_by_key(|schema| std::cmp::Reverse(schema.opened));
-
-                    if pagination != 0 {
-                        if let Some(slice) = records.get(..pagination) {
-                            let duration = start.elapsed();
-                            let response =
-                                data_with_response_time_for_slice(duration, slice, set.len());
-
-                            return Ok(HttpResponse::Ok().json(response));
-                        }
-                    }
-                    let duration = start.elapsed();
-                    let response = data_with_response_time_for_slice(duration, &records, set.len());
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+    if search.limit == Some(0) {
+        return Err(ApiError::validation("limit", "limit starts at 1"));
+    }
+    let params = search.filter_params();
+    let mut filter = RecordFilter::parse(&params, &processing_status_params(&params))?;
+    filter.filters.extend(processing_status_filter(&rtxn, &db_handles.db_data, &params)?);
+    let order = SortOrder::parse(search.sort.as_deref(), search.sort_key.as_deref())?;
+    let request = PageRequest {
+        cursor: search.cursor.as_deref(),
+        page: None,
+        limit: match search.limit {
+            Some(limit) => Some(limit),
+            None if filter.is_empty() || search.cursor.is_some() => Some(DEFAULT_PAGE_SIZE),
+            None => None,
+        },
+    };
 
-                if (sort.is_empty() || sort == "asc") && sort_key == "opened" {
-                    records.sort_by_key(|schema| schema.opened);
-
-                    if pagination != 0 {
-                        if let Some(slice) = records.get(..pagination) {
-                            let duration = start.elapsed();
-                            let response =
-                                data_with_response_time_for_slice(duration, slice, set.len());
-
-                            return Ok(HttpResponse::Ok().json(response));
-                        }
-                    }
-                    let duration = start.elapsed();
-                    let response = data_with_response_time_for_slice(duration, &records, set.len());
-
-                    return Ok(HttpResponse::Ok().json(response));
-                } else if sort == "dsc" && sort_key == "opened" {
-                    records.sort_by_key(|schema| std::cmp::Reverse(schema.opened));
-
-                    if pagination != 0 {
-                        if let Some(slice) = records.get(..pagination) {
-                            let duration = start.elapsed();
-                            let response =
-                                data_with_response_time_for_slice(duration, slice, set.len());
-
-                            return Ok(HttpResponse::Ok().json(response));
-                        }
-                    }
-                    let duration = start.elapsed();
-                    let response = data_with_response_time_for_slice(duration, &records, set.len());
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+    let response = listing_page(&rtxn, &db_handles, &filter, &order, &request, search.include_deleted, start)?;
 
-                if (sort.is_empty() || sort == "asc") && sort_key == "last_updated" {
-                    records.sort_by_key(|schema| schema.last_updated);
-
-                    if pagination != 0 {
-                        if let Some(slice) = records.get(..pagination) {
-                            let duration = start.elapsed();
-                            let response =
-                                data_with_response_time_for_slice(duration, slice, set.len());
-
-                            return Ok(HttpResponse::Ok().json(response));
-                        }
-                    }
-                    let duration = start.elapsed();
-                    let response = data_with_response_time_for_slice(duration, &records, set.len());
-
-                    return Ok(HttpResponse::Ok().json(response));
-                } else if sort == "dsc" && sort_key == "last_updated" {
-                    records.sort_by_key(|schema| std::cmp::Reverse(schema.last_updated));
-
-                    if pagination != 0 {
-                        if let Some(slice) = records.get(..pagination) {
-                            let duration = start.elapsed();
-                            let response =
-                                data_with_response_time_for_slice(duration, slice, set.len());
-
-                            return Ok(HttpResponse::Ok().json(response));
-                        }
-                    }
-                    let duration = start.elapsed();
-                    let response = data_with_response_time_for_slice(duration, &records, set.len());
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+    Ok(HttpResponse::Ok().json(response))
+}
 
-                if (sort.is_empty() || sort == "asc") && sort_key == "status_updated" {
-                    records.sort_by_key(|schema| schema.status_updated);
-
-                    if pagination != 0 {
-                        if let Some(slice) = records.get(..pagination) {
-                            let duration = start.elapsed();
-                            let response =
-                                data_with_response_time_for_slice(duration, slice, set.len());
-
-                            return Ok(HttpResponse::Ok().json(response));
-                        }
-                    }
-                    let duration = start.elapsed();
-                    let response = data_with_response_time_for_slice(duration, &records, set.len());
-
-                    return Ok(HttpResponse::Ok().json(response));
-                } else if sort == "dsc" && sort_key == "status_updated" {
-                    records.sort_by_key(|schema| std::cmp::Reverse(schema.status_updated));
-
-                    if pagination != 0 {
-                        if let Some(slice) = records.get(..pagination) {
-                            let duration = start.elapsed();
-                            let response =
-                                data_with_response_time_for_slice(duration, slice, set.len());
-
-                            return Ok(HttpResponse::Ok().json(response));
-                        }
-                    }
-                    let duration = start.elapsed();
-                    let response = data_with_response_time_for_slice(duration, &records, set.len());
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
+/// Full-text search over address, client, permit number and permit link.
+/// See [`TextQuery`] for the query syntax.
+#[get("/search")]
+pub async fn text_search(
+    db_env: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    query: web::Query<TextSearchQuery>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
 
-                if (sort.is_empty() || sort == "asc") && sort_key == "manual_status" {
-                    records.sort_by_key(|schema| schema.manual_status.clone());
-
-                    if pagination != 0 {
-                        if let Some(slice) = records.get(..pagination) {
-                            let duration = start.elapsed();
-                            let response =
-                                data_with_response_time_for_slice(duration, slice, set.len());
-
-                            return Ok(HttpResponse::Ok().json(response));
-                        }
-                    }
-                    let duration = start.elapsed();
-                    let response = data_with_response_time_for_slice(duration, &records, set.len());
-
-                    return Ok(HttpResponse::Ok().json(response));
-                } else if sort == "dsc" && sort_key == "manual_status" {
-                    records.sort_by_key(|schema| std::cmp::Reverse(schema.manual_status.clone()));
-
-                    if pagination != 0 {
-                        if let Some(slice) = records.get(..pagination) {
-                            let duration = start.elapsed();
-                            let response =
-                                data_with_response_time_for_slice(duration, slice, set.len());
-
-                            return Ok(HttpResponse::Ok().json(response));
-                        }
-                    }
-                    let duration = start.elapsed();
-                    let response = data_with_response_time_for_slice(duration, &records, set.len());
-
-                    return Ok(HttpResponse::Ok().json(response));
-                }
-            }
-            return Ok(HttpResponse::Ok().body("No Records Found"));
+    if query.limit == Some(0) {
+        return Err(ApiError::validation("limit", "limit starts at 1"));
+    }
+    let text_query = TextQuery::parse(&query.q)?;
+    let scored = text_query.run(&rtxn, &db_handles.db_data)?;
+    let total = scored.len();
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let page = page_of_results(scored, &query.q, query.cursor.as_deref(), limit)?;
+
+    let mut results = vec![];
+    for (key, score) in page.results {
+        if let Some(record) = db_handles.db_data.main_db.get(&rtxn, &key)? {
+            results.push(json!({ "key": key, "score": score, "record": record }));
         }
-    } else {
-        let records: Vec<(String, DBSchema)> = cursor
-            .take(50)
-            .filter_map(|res| res.ok().map(|(key, value)| (key.to_string(), value)))
-            .collect();
-
-        let duration = start.elapsed();
-        let response = data_with_response_time(duration, records, entries);
-
-        return Ok(HttpResponse::Ok().json(response));
     }
 
-    Ok(HttpResponse::Ok().body("Couldn't read From DataBase"))
+    let duration = start.elapsed();
+    Ok(HttpResponse::Ok().json(json!({
+        "Response_time": duration.as_micros(),
+        "Number_of_records": total,
+        "data": results,
+        "next_cursor": page.next_cursor
+    })))
 }
 
-fn helper_function_for_retrieving_data(
-    set: &HashSet<String>,
+/// One page of the records matching `filter`, with the `next_cursor` to
+/// continue from.
+fn listing_page(
     rtxn: &RoTxn,
-    db_handles: &web::Data<DBdata>,
-) -> Result<Vec<DBSchema>, Box<dyn std::error::Error>> {
-    let mut storage: Vec<DBSchema> = vec![];
+    db_handles: &DBdata,
+    filter: &RecordFilter,
+    order: &SortOrder,
+    request: &PageRequest,
+    include_deleted: bool,
+    start: std::time::Instant,
+) -> Result<serde_json::Value, ApiError> {
+    let page = page_of_matches(rtxn, &db_handles.db_data, filter, order, request)?;
 
-    for each in set {
-        let value = handle!(db_handles.db_data.main_db.get(rtxn, each));
+    let duration = start.elapsed();
+    let mut response = data_with_response_time(duration, page.records, page.total);
+    response["next_cursor"] = json!(page.next_cursor);
+    if include_deleted {
+        let deleted_records = collect_deleted_matches(rtxn, &db_handles.db_data, filter)?;
+        response["deleted_data"] = json!(deleted_records);
+    }
 
-        if let Some(value) = value {
-            storage.push(value);
-        } else {
-            println!("couldn't find the value for uuid: {each}")
-        }
+    Ok(response)
+}
+
+fn export_listing(
+    req: &HttpRequest,
+    db_env: &DbEnv,
+    db_handles: &DBdata,
+    params: HashMap<String, String>,
+    date_range_required: bool,
+) -> Result<HttpResponse, ApiError> {
+    if date_range_required {
+        require_date_range(&params)?;
     }
-    Ok(storage)
+
+    let filter = RecordFilter::parse(&params, EXPORT_PARAMS)?;
+    let order = SortOrder::parse(params.get("sort").map(String::as_str), None)?;
+    let format = ExportFormat::negotiate(req, &params)?;
+    let include_deleted = parse_include_deleted(&params)?;
+    let columns = parse_columns(&params, include_deleted)?;
+
+    Ok(stream_matches(
+        db_env.env.clone(),
+        db_handles.db_data.clone(),
+        filter,
+        order,
+        include_deleted,
+        format,
+        columns,
+    ))
+}
+
+#[get("/export/read-record")]
+pub async fn export_records(
+    req: HttpRequest,
+    db_env: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    params: web::Query<HashMap<String, String>>,
+) -> Result<impl Responder, ApiError> {
+    export_listing(&req, &db_env, &db_handles, params.into_inner(), false)
+}
+
+#[get("/export/read-permits-with-filter")]
+pub async fn export_permits_with_filter(
+    req: HttpRequest,
+    db_env: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    params: web::Query<HashMap<String, String>>,
+) -> Result<impl Responder, ApiError> {
+    export_listing(&req, &db_env, &db_handles, params.into_inner(), false)
+}
+
+#[get("/export/read-records-by-opened-date")]
+pub async fn export_records_by_opened_date(
+    req: HttpRequest,
+    db_env: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    params: web::Query<HashMap<String, String>>,
+) -> Result<impl Responder, ApiError> {
+    export_listing(
+        &req,
+        &db_env,
+        &db_handles,
+        params.into_inner(),
+        true,
+    )
 }
 
 #[put("/update-record/{uuid}")]
 pub async fn update_records(
     db_env: web::Data<DbEnv>,
     db_handles: web::Data<DBdata>,
+    config: web::Data<AppConfig>,
+    audit: AuditContext,
+    if_match: IfMatchRevision,
     path: web::Path<String>,
     updated_data: web::Json<UpdateDBSchema>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
-    let mut wtxn = handle!(db_env.env.write_txn());
+) -> Result<impl Responder, ApiError> {
+    let mut wtxn = db_env.env.write_txn()?;
     let uuid = path.into_inner();
 
-    let main_record = handle!(db_handles.db_data.main_db.get(&wtxn, &uuid));
+    let main_record = db_handles.db_data.main_db.get(&wtxn, &uuid)?;
 
     if let Some(mut data) = main_record {
-        let key = KeySchema {
-            county: data.county.to_owned(),
-            client: data.client.to_owned(),
-            county_status: data.county_status.clone(),
+        if_match.check(&uuid, data.revision)?;
+
+        if let Some(permit_number) = &updated_data.permit_number
+            && let Some(existing_key) = permit_number_owner(
+                &db_handles.db_data,
+                &wtxn,
+                permit_number,
+                Some(&uuid)
+            )?
+        {
+            return Err(ApiError::permit_conflict(permit_number, &existing_key));
+        }
+
+        // Processing states and payments are keyed by permit number, so a
+        // renamed permit would leave them behind under the old one.
+        if let Some(permit_number) = &updated_data.permit_number
+            && *permit_number != data.permit_number
+        {
+            let processing_keys = child_keys(db_handles.db_data.processing_state, &wtxn, &data.permit_number)?;
+            let payment_keys = child_keys(db_handles.db_data.payments_db, &wtxn, &data.permit_number)?;
+            if !processing_keys.is_empty() || !payment_keys.is_empty() {
+                return Err(ApiError::conflict(
+                    "permit_number",
+                    format!(
+                        "permit {} cannot be renumbered while it has {} processing states and {} payments",
+                        data.permit_number,
+                        processing_keys.len(),
+                        payment_keys.len()
+                    ),
+                )
+                .with_detail("processing_states", processing_keys.len())
+                .with_detail("payments", payment_keys.len()));
+            }
+        }
+
+        let format = "%Y-%m-%dT%H:%M:%S%.3f";
+        let status_updated = match &updated_data.status_updated {
+            Some(status_updated) => match NaiveDateTime::parse_from_str(status_updated, format) {
+                Ok(naive_dt) => Some(naive_dt),
+                Err(_) => {
+                    return Err(ApiError::validation(
+                        "status_updated",
+                        "The format for status_updated is wrong. Ensure you are using this format: %Y-%m-%dT%H:%M:%S%.3f",
+                    ));
+                }
+            },
+            None => None,
         };
 
-        let set = handle!(db_handles.db_data.composite_index.get(&wtxn, &key));
-        if let Some(mut set) = set {
-            set.remove(&uuid.clone());
-            handle!(
-                db_handles
-                    .db_data
-                    .composite_index
-                    .put(&mut wtxn, &key, &mut set)
-            );
-        } else {
-            return Ok(HttpResponse::Ok().body("The UUID is not Valid".to_string()));
+        let now = chrono::Utc::now().naive_utc();
+        let mut moved = vec![];
+        for (field, from, to) in [
+            (StatusField::CountyStatus, &data.county_status, &updated_data.county_status),
+            (StatusField::ManualStatus, &data.manual_status, &updated_data.manual_status),
+        ] {
+            if let Some(to) = to
+                && config.transitions.check(field, from, to, data.status_updated, now, updated_data.status_reason.as_deref())?
+            {
+                moved.push((field, from.clone(), to.clone()));
+            }
+        }
+
+        // status_updated is what min_days_in_status is measured from, so it
+        // is stamped on every transition and can't be moved back by hand.
+        if let Some(status_updated) = status_updated {
+            if !moved.is_empty() {
+                return Err(ApiError::validation(
+                    "status_updated",
+                    "status_updated is set automatically when county_status or manual_status changes",
+                ));
+            }
+            if status_updated < data.status_updated {
+                return Err(ApiError::validation(
+                    "status_updated",
+                    format!("status_updated cannot be moved back from {:?}", data.status_updated),
+                ));
+            }
+            if status_updated > now {
+                return Err(ApiError::validation("status_updated", "status_updated cannot be in the future"));
+            }
         }
 
+        let before = data.clone();
+        unindex_record(&db_handles.db_data, &mut wtxn, &uuid, &data)?;
+
         if let Some(permit_link) = &updated_data.permit_link {
             data.permit_link = permit_link.to_string();
         }
@@ -994,86 +1427,89 @@ pub async fn update_records(
         }
         if let Some(address) = &updated_data.address {
             data.address = address.to_string();
+            data.normalized_address = normalize_address(address);
         }
 
-        let format = "%Y-%m-%dT%H:%M:%S%.3f";
         if let Some(last_updated) = &updated_data.last_updated {
             match NaiveDateTime::parse_from_str(last_updated, format) {
                 Ok(naive_dt) => data.last_updated = naive_dt,
                 Err(_) => {
-                    return Ok(HttpResponse::Ok().body("The format for last_updated is wrong\nEnsure you are using this format: %Y-%m-%dT%H:%M:%S%.3f"))
+                    return Err(ApiError::validation(
+                        "last_updated",
+                        "The format for last_updated is wrong. Ensure you are using this format: %Y-%m-%dT%H:%M:%S%.3f",
+                    ));
                 }
             }
         }
-        if let Some(status_updated) = &updated_data.status_updated {
-            match NaiveDateTime::parse_from_str(status_updated, format) {
-                Ok(naive_dt) => data.status_updated = naive_dt,
-                Err(_) => {
-                    return Ok(HttpResponse::Ok().body("The format for status_updated is wrong\nEnsure you are using this format: %Y-%m-%dT%H:%M:%S%.3f"))
-                }
-            }
+        if !moved.is_empty() {
+            data.status_updated = now;
+        } else if let Some(status_updated) = status_updated {
+            data.status_updated = status_updated;
         }
 
-        let new_key = KeySchema {
-            county: data.county.to_owned(),
-            county_status: data.county_status.to_owned(),
-            client: data.client.to_owned(),
-        };
-
-        let new_set = handle!(db_handles.db_data.composite_index.get(&wtxn, &new_key));
-
-        if let Some(mut new_set) = new_set {
-            new_set.insert(uuid.clone());
-            handle!(
-                db_handles
-                    .db_data
-                    .composite_index
-                    .put(&mut wtxn, &new_key, &mut new_set)
-            );
-        } else {
-            let mut new_set: HashSet<String> = HashSet::new();
-            new_set.insert(uuid.clone());
-            handle!(
-                db_handles
-                    .db_data
-                    .composite_index
-                    .put(&mut wtxn, &new_key, &mut new_set)
-            );
-        }
-
-        handle!(
-            db_handles
-                .db_data
-                .main_db
-                .put(&mut wtxn, &uuid, &data.clone())
-        );
+        data.revision += 1;
+        index_record(&db_handles.db_data, &mut wtxn, &uuid, &data)?;
+
+        db_handles.db_data.main_db.put(&mut wtxn, &uuid, &data.clone())?;
+        record_change(
+            &db_handles.db_data,
+            &mut wtxn,
+            &audit,
+            Change::updated(MAIN_DB, &uuid, &before, &data)
+        )?;
+        let transitions = moved
+            .into_iter()
+            .map(|(field, from, to)| StatusTransition {
+                field,
+                from,
+                to,
+                at: data.status_updated,
+                actor: audit.actor.clone(),
+                reason: updated_data.status_reason.clone(),
+            })
+            .collect();
+        record_transitions(&db_handles.db_data, &mut wtxn, &uuid, transitions)?;
 
         let start = std::time::Instant::now();
-        handle!(wtxn.commit());
+        wtxn.commit()?;
         let duration = start.elapsed();
 
-        return Ok(HttpResponse::Ok().body(format!(
+        return Ok(HttpResponse::Ok().insert_header(etag(data.revision)).body(format!(
             "Successfully Updated the Record\nResponse Time: {}",
             duration.as_micros()
         )));
     }
-    Ok(HttpResponse::Ok().body("Failed to update the Record\nNo Record Exists"))
+    Err(ApiError::not_found(format!("Failed to update the Record, no record exists with the uuid: {uuid}")))
 }
 
 #[put("/update-processing-status/{permit_number}/{last_updated}")]
 pub async fn update_processing_status(
     db_env: web::Data<DbEnv>,
     db_handles: web::Data<DBdata>,
+    audit: AuditContext,
+    if_match: IfMatchRevision,
     path: web::Path<(String, String)>,
     updated_data: web::Json<UpdateProcessingStatusSchema>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
-    let mut wtxn = handle!(db_env.env.write_txn());
+) -> Result<impl Responder, ApiError> {
+    let mut wtxn = db_env.env.write_txn()?;
     let path = path.into_inner();
     let mut key = format!("{}-{}", path.0, path.1);
+    let previous_key = key.clone();
 
-    let record = handle!(db_handles.db_data.processing_state.get(&mut wtxn, &key));
+    let record = db_handles.db_data.processing_state.get(&wtxn, &key)?;
 
-    if let Some(mut record) = record {
+    let revision = if let Some(mut record) = record {
+        if_match.check(&key, record.revision)?;
+
+        if let Some(processing_status) = &updated_data.processing_status {
+            let county = permit_county(&wtxn, &db_handles.db_data, &path.0)?;
+            validate_stage(&wtxn, &db_handles.db_data, county.as_deref(), processing_status)?;
+        }
+
+        let before = record.clone();
+        unindex_current_state(&db_handles.db_data, &mut wtxn, &path.0)?;
+        unindex_assignee(&db_handles.db_data, &mut wtxn, &key, &record)?;
+        record.revision += 1;
         if let Some(processing_status) = updated_data.processing_status.to_owned() {
             record.processing_status = processing_status
         }
@@ -1085,28 +1521,33 @@ pub async fn update_processing_status(
         }
         if let Some(last_modified) = updated_data.last_modified {
             record.last_modified = last_modified;
-            handle!(db_handles.db_data.processing_state.delete(&mut wtxn, &key));
+            db_handles.db_data.processing_state.delete(&mut wtxn, &key)?;
             key = format!("{}-{:?}", path.0, last_modified);
         }
 
-        handle!(
-            db_handles
-                .db_data
-                .processing_state
-                .put(&mut wtxn, &key, &record)
-        );
+        db_handles.db_data.processing_state.put(&mut wtxn, &key, &record)?;
+        index_assignee(&db_handles.db_data, &mut wtxn, &key, &record)?;
+        index_current_state(&db_handles.db_data, &mut wtxn, &path.0)?;
+        record_change(
+            &db_handles.db_data,
+            &mut wtxn,
+            &audit,
+            Change::updated(PROCESSING_STATE_DB, &key, &before, &record)
+                .with_previous_key(Some(&previous_key))
+        )?;
+        record.revision
     } else {
-        return Ok(HttpResponse::Ok().body(format!(
+        return Err(ApiError::not_found(format!(
             "No record found with the permit number: {}",
             path.0
         )));
-    }
+    };
 
     let start = std::time::Instant::now();
-    handle!(wtxn.commit());
+    wtxn.commit()?;
     let duration = start.elapsed().as_micros();
 
-    Ok(HttpResponse::Ok().body(format!(
+    Ok(HttpResponse::Ok().insert_header(etag(revision)).body(format!(
         "Successfully updated the processing state\nResponse Time: {duration}"
     )))
 }
@@ -1115,16 +1556,23 @@ pub async fn update_processing_status(
 pub async fn update_payment_details(
     db_handles: web::Data<DBdata>,
     db_env: web::Data<DbEnv>,
+    audit: AuditContext,
+    if_match: IfMatchRevision,
     path: web::Path<(String, String)>,
     updated_data: web::Json<UpdatePayment>,
-) -> Result<impl Responder, Box<dyn std::error::Error>> {
-    let mut wtxn = handle!(db_env.env.write_txn());
+) -> Result<impl Responder, ApiError> {
+    let mut wtxn = db_env.env.write_txn()?;
     let path = path.into_inner();
     let mut key = format!("{}-{}", path.0, path.1);
+    let previous_key = key.clone();
 
-    let record = handle!(db_handles.db_data.payments_db.get(&wtxn, &key));
+    let record = db_handles.db_data.payments_db.get(&wtxn, &key)?;
 
-    if let Some(mut record) = record {
+    let revision = if let Some(mut record) = record {
+        if_match.check(&key, record.revision)?;
+
+        let before = record.clone();
+        record.revision += 1;
         if let Some(payment) = updated_data.payment.to_owned() {
             record.payment = payment;
         }
@@ -1136,24 +1584,32 @@ pub async fn update_payment_details(
         }
         if let Some(date) = updated_data.date {
             record.date = date;
-            handle!(db_handles.db_data.payments_db.delete(&mut wtxn, &key));
+            db_handles.db_data.payments_db.delete(&mut wtxn, &key)?;
             println!("{key}");
             key = format!("{}-{:?}", path.0, date);
             println!("{key}");
         }
-        handle!(db_handles.db_data.payments_db.put(&mut wtxn, &key, &record));
+        db_handles.db_data.payments_db.put(&mut wtxn, &key, &record)?;
+        record_change(
+            &db_handles.db_data,
+            &mut wtxn,
+            &audit,
+            Change::updated(PAYMENTS_DB, &key, &before, &record)
+                .with_previous_key(Some(&previous_key))
+        )?;
+        record.revision
     } else {
-        return Ok(HttpResponse::Ok().body(format!(
+        return Err(ApiError::not_found(format!(
             "No record found with the permit number: {}",
             path.0
         )));
-    }
+    };
 
     let start = std::time::Instant::now();
-    handle!(wtxn.commit());
+    wtxn.commit()?;
     let duration = start.elapsed().as_micros();
 
-    Ok(HttpResponse::Ok().body(format!(
+    Ok(HttpResponse::Ok().insert_header(etag(revision)).body(format!(
         "Successfully updated the processing state\nResponse Time: {duration}"
     )))
 }
@@ -1162,52 +1618,281 @@ pub async fn update_payment_details(
 pub async fn delete_record(
     db_env: web::Data<DbEnv>,
     db_handles: web::Data<DBdata>,
+    config: web::Data<AppConfig>,
+    audit: AuditContext,
     path: web::Path<String>,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let mut wtxn = handle!(db_env.env.write_txn());
+    options: web::Query<DeleteOptions>,
+) -> Result<impl Responder, ApiError> {
+    let mut wtxn = db_env.env.write_txn()?;
     let uuid = path.into_inner();
+    let policy = options.policy.unwrap_or(config.delete_policy);
 
-    let main_data = handle!(db_handles.db_data.main_db.get(&wtxn, &uuid));
+    let main_data = db_handles.db_data.main_db.get(&wtxn, &uuid)?;
 
     if let Some(record) = main_data {
-        let key = KeySchema {
-            county: record.county.to_owned(),
-            client: record.client.to_owned(),
-            county_status: record.county_status.clone(),
+        let processing_keys = child_keys(
+            db_handles.db_data.processing_state,
+            &wtxn,
+            &record.permit_number
+        )?;
+        let payment_keys = child_keys(
+            db_handles.db_data.payments_db,
+            &wtxn,
+            &record.permit_number
+        )?;
+
+        let (processing_deleted, payments_deleted) = match policy {
+            DeletePolicy::Restrict if !processing_keys.is_empty() || !payment_keys.is_empty() => {
+                return Err(ApiError::conflict(
+                    "permit_number",
+                    format!(
+                        "permit {} still has {} processing states and {} payments",
+                        record.permit_number,
+                        processing_keys.len(),
+                        payment_keys.len()
+                    ),
+                )
+                .with_detail("processing_states", processing_keys.len())
+                .with_detail("payments", payment_keys.len()));
+            }
+            DeletePolicy::Cascade => delete_child_rows(
+                &db_handles.db_data,
+                &mut wtxn,
+                &audit,
+                &record.permit_number
+            )?,
+            DeletePolicy::Soft => {
+                let tombstone = Tombstone {
+                    record: record.clone(),
+                    deleted_at: chrono::Utc::now().naive_utc(),
+                };
+                db_handles.db_data.deleted_db.put(&mut wtxn, &uuid, &tombstone)?;
+                db_handles.db_data.deleted_permit_index.put(&mut wtxn, &record.permit_number, &uuid)?;
+                (0, 0)
+            }
+            DeletePolicy::Restrict => (0, 0),
         };
 
-        let set = handle!(db_handles.db_data.composite_index.get(&wtxn, &key));
-        if let Some(mut set) = set {
-            set.remove(&uuid.clone());
-            handle!(
-                db_handles
-                    .db_data
-                    .composite_index
-                    .put(&mut wtxn, &key, &set)
-            );
-        } else {
-            return Ok("The UUID is not in the DataBase".to_string());
-        }
+        unindex_record(&db_handles.db_data, &mut wtxn, &uuid, &record)?;
+        db_handles.db_data.main_db.delete(&mut wtxn, &uuid)?;
 
-        handle!(db_handles.db_data.main_db.delete(&mut wtxn, &uuid));
+        let action = match policy {
+            DeletePolicy::Soft => AuditAction::SoftDelete,
+            _ => AuditAction::Delete,
+        };
+        record_change(
+            &db_handles.db_data,
+            &mut wtxn,
+            &audit,
+            Change::deleted(MAIN_DB, &uuid, &record).with_action(action)
+        )?;
 
         let start = std::time::Instant::now();
-        handle!(wtxn.commit());
+        wtxn.commit()?;
         let duration = start.elapsed();
 
-        return Ok(format!(
-            "Successfully Deleted the record\nResponse Time: {}",
+        Ok(HttpResponse::Ok().body(format!(
+            "Successfully Deleted the record\nPolicy: {policy}\nProcessing states deleted: {processing_deleted}\nPayments deleted: {payments_deleted}\nResponse Time: {}",
             duration.as_micros()
-        ));
+        )))
     } else {
-        return Ok("Couldn't Delete the record".to_string());
+        Err(ApiError::not_found(format!("Couldn't Delete the record, no record exists with the uuid: {uuid}")))
+    }
+}
+
+#[post("/restore-record/{uuid}")]
+pub async fn restore_record(
+    db_env: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    audit: AuditContext,
+    path: web::Path<String>,
+) -> Result<impl Responder, ApiError> {
+    let mut wtxn = db_env.env.write_txn()?;
+    let uuid = path.into_inner();
+
+    let tombstone = match db_handles.db_data.deleted_db.get(&wtxn, &uuid)? {
+        Some(tombstone) => tombstone,
+        None => return Err(ApiError::not_found(format!("No deleted Record found with the uuid: {uuid}"))),
+    };
+    let record = tombstone.record;
+
+    if let Some(existing_key) = permit_number_owner(
+        &db_handles.db_data,
+        &wtxn,
+        &record.permit_number,
+        Some(&uuid)
+    )? {
+        return Err(ApiError::permit_conflict(&record.permit_number, &existing_key));
+    }
+
+    db_handles.db_data.main_db.put(&mut wtxn, &uuid, &record)?;
+    index_record(&db_handles.db_data, &mut wtxn, &uuid, &record)?;
+    db_handles.db_data.deleted_db.delete(&mut wtxn, &uuid)?;
+    if db_handles.db_data.deleted_permit_index.get(&wtxn, &record.permit_number)? == Some(uuid.as_str()) {
+        db_handles.db_data.deleted_permit_index.delete(&mut wtxn, &record.permit_number)?;
     }
+    record_change(
+        &db_handles.db_data,
+        &mut wtxn,
+        &audit,
+        Change::created(MAIN_DB, &uuid, &record).with_action(AuditAction::Restore)
+    )?;
+
+    let start = std::time::Instant::now();
+    wtxn.commit()?;
+    let duration = start.elapsed();
+
+    Ok(HttpResponse::Ok().body(format!(
+        "Successfully Restored the record\nResponse Time: {}",
+        duration.as_micros()
+    )))
+}
+
+#[delete("/purge-deleted-records")]
+pub async fn purge_deleted_records(
+    db_env: web::Data<DbEnv>,
+    db_handles: web::Data<DBdata>,
+    config: web::Data<AppConfig>,
+    audit: AuditContext,
+    options: web::Query<PurgeOptions>,
+) -> Result<impl Responder, ApiError> {
+    let retention_days = options.older_than_days.unwrap_or(config.tombstone_retention_days);
+    let cutoff = i64::try_from(retention_days)
+        .ok()
+        .and_then(chrono::TimeDelta::try_days)
+        .and_then(|retention| chrono::Utc::now().naive_utc().checked_sub_signed(retention))
+        .ok_or_else(|| ApiError::validation("older_than_days", format!("{retention_days} days is too far back")))?;
+    let mut wtxn = db_env.env.write_txn()?;
+
+    let mut expired = vec![];
+    for entry in db_handles.db_data.deleted_db.iter(&wtxn)? {
+        let (key, tombstone) = entry?;
+        if tombstone.deleted_at < cutoff {
+            expired.push((key.to_string(), tombstone.record));
+        }
+    }
+
+    let mut processing_deleted = 0;
+    let mut payments_deleted = 0;
+
+    for (key, record) in &expired {
+        let (processing, payments) = delete_child_rows(
+            &db_handles.db_data,
+            &mut wtxn,
+            &audit,
+            &record.permit_number
+        )?;
+        processing_deleted += processing;
+        payments_deleted += payments;
+
+        db_handles.db_data.deleted_db.delete(&mut wtxn, key)?;
+        if db_handles.db_data.deleted_permit_index.get(&wtxn, &record.permit_number)? == Some(key.as_str()) {
+            db_handles.db_data.deleted_permit_index.delete(&mut wtxn, &record.permit_number)?;
+        }
+        record_change(
+            &db_handles.db_data,
+            &mut wtxn,
+            &audit,
+            Change::deleted(MAIN_DB, key, record).with_action(AuditAction::Purge)
+        )?;
+    }
+
+    let start = std::time::Instant::now();
+    wtxn.commit()?;
+    let duration = start.elapsed();
+
+    Ok(HttpResponse::Ok().body(format!(
+        "Successfully Purged {} records deleted more than {retention_days} days ago\nProcessing states deleted: {processing_deleted}\nPayments deleted: {payments_deleted}\nResponse Time: {}",
+        expired.len(),
+        duration.as_micros()
+    )))
+}
+
+#[get("/audit/{key}")]
+pub async fn read_audit_for_key(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+    path: web::Path<String>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
+    let key = path.into_inner();
+
+    let ids = audit_ids(&rtxn, &db_handles.db_data, &key)?;
+    let mut entries = vec![];
+
+    for id in &ids {
+        if let Some(entry) = db_handles.db_data.audit_log.get(&rtxn, id)? {
+            entries.push(audit_entry_json(id, &entry));
+        }
+    }
+
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "Number_of_records": entries.len(),
+        "Data": entries
+    });
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[get("/audit")]
+pub async fn read_audit_log(
+    db_handles: web::Data<DBdata>,
+    db_env: web::Data<DbEnv>,
+    query: web::Query<AuditQuery>,
+) -> Result<impl Responder, ApiError> {
+    let start = std::time::Instant::now();
+    let rtxn = db_env.env.read_txn()?;
+
+    let since = match query.since.as_deref() {
+        Some(since) => match parse_datetime(since) {
+            Some(since) => Some(format!("{since:?}")),
+            None => return Err(ApiError::validation("since", date_error_message(since))),
+        },
+        None => None,
+    };
+    let limit = query.limit.unwrap_or(1000);
+    if limit == 0 {
+        return Err(ApiError::validation("limit", "limit starts at 1"));
+    }
+
+    // Entry ids start with their timestamp, so `after` only narrows `since`
+    // down when it is past it.
+    let lower = match (since.as_deref(), query.after.as_deref()) {
+        (Some(since), Some(after)) if after < since => Bound::Included(since),
+        (_, Some(after)) => Bound::Excluded(after),
+        (Some(since), None) => Bound::Included(since),
+        (None, None) => Bound::Unbounded,
+    };
+    let bounds = (lower, Bound::Unbounded);
+
+    let mut page = db_handles
+        .db_data
+        .audit_log
+        .range(&rtxn, &bounds)?
+        .take(limit.saturating_add(1))
+        .collect::<heed::Result<Vec<_>>>()?;
+    let next = (page.len() > limit).then(|| page[limit - 1].0.to_string());
+    page.truncate(limit);
+    let entries: Vec<_> = page.iter().map(|(id, entry)| audit_entry_json(id, entry)).collect();
+
+    let duration = start.elapsed().as_micros();
+    let response = json!({
+        "Response Time": duration,
+        "Number_of_records": entries.len(),
+        "next": next,
+        "Data": entries
+    });
+
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[get("/load-the-db")]
-async fn load_the_db() -> Result<impl Responder, Box<dyn std::error::Error>> {
+async fn load_the_db() -> Result<impl Responder, ApiError> {
     match loader().await {
         Ok(_) => Ok(HttpResponse::Ok().body("Successfully Loaded the records")),
-        Err(_) => Ok(HttpResponse::Ok().body("Failed to Load the Data")),
+        Err(e) => Err(ApiError::Internal(format!("Failed to Load the Data: {e}"))),
     }
 }
\ No newline at end of file
@@ -0,0 +1,350 @@
+use std::{collections::HashMap, ops::ControlFlow, sync::Arc, time::Duration};
+
+use actix_web::{HttpRequest, HttpResponse, http::header, web::Bytes};
+use chrono::NaiveDateTime;
+use futures::{SinkExt, channel::mpsc};
+use heed::Env;
+use serde_json::{Map, Value};
+
+use crate::{
+    pagination::SortOrder,
+    query_engine::{collect_deleted_matches, collect_matches, for_each_deleted_match, for_each_match},
+    struct_definitions::{DBHandles, DBSchema, FilterError, RecordFilter},
+};
+
+/// Query parameters that shape the export rather than filter it.
+pub const EXPORT_PARAMS: &[&str] = &["format", "columns", "sort", "include_deleted"];
+
+/// Columns available to `columns=`, in their default order.
+pub const EXPORT_COLUMNS: &[&str] = &[
+    "key",
+    "permit_link",
+    "permit_number",
+    "client",
+    "opened",
+    "last_updated",
+    "status_updated",
+    "county",
+    "county_status",
+    "manual_status",
+    "address",
+    "normalized_address",
+    "revision",
+];
+
+/// Extra column of `include_deleted=true` exports, empty on live records.
+pub const DELETED_AT_COLUMN: &str = "deleted_at";
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How long a client may go without reading before its export is dropped,
+/// so a stalled download doesn't hold its read transaction open.
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+type ExportError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    /// `format=` takes precedence over the `Accept` header. CSV is the default.
+    pub fn negotiate(req: &HttpRequest, params: &HashMap<String, String>) -> Result<Self, FilterError> {
+        if let Some(format) = params.get("format") {
+            return match format.to_lowercase().as_str() {
+                "csv" => Ok(ExportFormat::Csv),
+                "ndjson" | "jsonl" => Ok(ExportFormat::Ndjson),
+                _ => Err(FilterError {
+                    code: "invalid_value",
+                    field: "format".to_string(),
+                    message: format!("Unknown export format {format}, expected csv or ndjson"),
+                }),
+            };
+        }
+
+        let accept = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+        if accept.contains("ndjson") || accept.contains("jsonl") {
+            Ok(ExportFormat::Ndjson)
+        } else {
+            Ok(ExportFormat::Csv)
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+/// Reads `include_deleted=true|false`, which also appends soft-deleted
+/// records to the export.
+pub fn parse_include_deleted(params: &HashMap<String, String>) -> Result<bool, FilterError> {
+    match params.get("include_deleted") {
+        None => Ok(false),
+        Some(value) => value.trim().parse().map_err(|_| FilterError {
+            code: "invalid_value",
+            field: "include_deleted".to_string(),
+            message: format!("'{value}' is not a boolean, expected true or false"),
+        }),
+    }
+}
+
+/// Reads `columns=a,b,c`, defaulting to every column in [`EXPORT_COLUMNS`],
+/// plus [`DELETED_AT_COLUMN`] when tombstones are included.
+pub fn parse_columns(params: &HashMap<String, String>, include_deleted: bool) -> Result<Vec<String>, FilterError> {
+    let Some(columns) = params.get("columns") else {
+        let mut columns: Vec<String> = EXPORT_COLUMNS.iter().map(|column| column.to_string()).collect();
+        if include_deleted {
+            columns.push(DELETED_AT_COLUMN.to_string());
+        }
+        return Ok(columns);
+    };
+
+    let columns = columns
+        .split(',')
+        .map(str::trim)
+        .filter(|column| !column.is_empty())
+        .map(|column| match EXPORT_COLUMNS.contains(&column) || column == DELETED_AT_COLUMN {
+            true => Ok(column.to_string()),
+            false => Err(FilterError {
+                code: "unknown_field",
+                field: column.to_string(),
+                message: format!("Unknown export column {column}"),
+            }),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if columns.is_empty() {
+        return Err(FilterError {
+            code: "invalid_value",
+            field: "columns".to_string(),
+            message: "columns must name at least one column".to_string(),
+        });
+    }
+
+    Ok(columns)
+}
+
+fn row(key: &str, record: &DBSchema, deleted_at: Option<NaiveDateTime>, columns: &[String]) -> Map<String, Value> {
+    let mut fields = match serde_json::to_value(record) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    };
+    fields.insert("key".to_string(), Value::String(key.to_string()));
+    if let Some(deleted_at) = deleted_at {
+        fields.insert(DELETED_AT_COLUMN.to_string(), Value::String(format!("{deleted_at:?}")));
+    }
+
+    columns
+        .iter()
+        .map(|column| (column.to_owned(), fields.remove(column).unwrap_or(Value::Null)))
+        .collect()
+}
+
+enum Encoder {
+    Csv(Box<csv::Writer<Vec<u8>>>),
+    Ndjson(Vec<u8>),
+}
+
+impl Encoder {
+    fn new(format: ExportFormat, columns: &[String]) -> Result<Self, ExportError> {
+        match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(vec![]);
+                writer.write_record(columns)?;
+                Ok(Encoder::Csv(Box::new(writer)))
+            }
+            ExportFormat::Ndjson => Ok(Encoder::Ndjson(vec![])),
+        }
+    }
+
+    fn write(&mut self, row: Map<String, Value>, columns: &[String]) -> Result<(), ExportError> {
+        match self {
+            Encoder::Csv(writer) => writer.write_record(columns.iter().map(|column| match &row[column] {
+                Value::String(text) => text.to_owned(),
+                Value::Null => String::new(),
+                value => value.to_string(),
+            }))?,
+            Encoder::Ndjson(buffer) => {
+                serde_json::to_writer(&mut *buffer, &row)?;
+                buffer.push(b'\n');
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes whatever has been encoded so far once it reaches `at_least` bytes.
+    fn take(&mut self, at_least: usize) -> Result<Option<Bytes>, ExportError> {
+        match self {
+            Encoder::Csv(writer) => {
+                writer.flush()?;
+                if writer.get_ref().is_empty() || writer.get_ref().len() < at_least {
+                    return Ok(None);
+                }
+                let full = std::mem::replace(writer, Box::new(csv::Writer::from_writer(vec![])));
+                let buffer = full.into_inner().map_err(|e| e.into_error())?;
+                Ok(Some(Bytes::from(buffer)))
+            }
+            Encoder::Ndjson(buffer) => {
+                if buffer.is_empty() || buffer.len() < at_least {
+                    return Ok(None);
+                }
+                Ok(Some(Bytes::from(std::mem::take(buffer))))
+            }
+        }
+    }
+}
+
+type Sender = mpsc::Sender<Result<Bytes, std::io::Error>>;
+
+/// Hands a chunk to the response from the export's blocking thread. Breaks
+/// when the client has gone away or stopped reading for [`SEND_TIMEOUT`].
+fn send(sender: &mut Sender, chunk: Result<Bytes, std::io::Error>) -> ControlFlow<()> {
+    let send = tokio::time::timeout(SEND_TIMEOUT, sender.send(chunk));
+    match tokio::runtime::Handle::current().block_on(send) {
+        Ok(Ok(())) => ControlFlow::Continue(()),
+        Ok(Err(_)) => ControlFlow::Break(()),
+        Err(_) => {
+            eprintln!("Export stopped, the client read nothing for {SEND_TIMEOUT:?}");
+            ControlFlow::Break(())
+        }
+    }
+}
+
+/// Encodes rows and sends them on in chunks, remembering whether the client
+/// went away or encoding failed so the rest of the export can be skipped.
+struct RowWriter<'a> {
+    encoder: Encoder,
+    columns: &'a [String],
+    sender: &'a mut Sender,
+    failure: Option<ExportError>,
+    stopped: bool,
+}
+
+impl<'a> RowWriter<'a> {
+    fn new(format: ExportFormat, columns: &'a [String], sender: &'a mut Sender) -> Result<Self, ExportError> {
+        Ok(RowWriter {
+            encoder: Encoder::new(format, columns)?,
+            columns,
+            sender,
+            failure: None,
+            stopped: false,
+        })
+    }
+
+    fn write(&mut self, key: &str, record: &DBSchema, deleted_at: Option<NaiveDateTime>) -> ControlFlow<()> {
+        let chunk = self
+            .encoder
+            .write(row(key, record, deleted_at, self.columns), self.columns)
+            .and_then(|_| self.encoder.take(CHUNK_SIZE));
+
+        let flow = match chunk {
+            Ok(Some(chunk)) => send(self.sender, Ok(chunk)),
+            Ok(None) => ControlFlow::Continue(()),
+            Err(e) => {
+                self.failure = Some(e);
+                ControlFlow::Break(())
+            }
+        };
+        self.stopped |= flow.is_break();
+        flow
+    }
+
+    fn finish(mut self) -> Result<(), ExportError> {
+        if let Some(e) = self.failure {
+            return Err(e);
+        }
+        if let Some(chunk) = self.encoder.take(0)? {
+            // The client may already have gone away, in which case there is no one to tell.
+            let _ = send(self.sender, Ok(chunk));
+        }
+
+        Ok(())
+    }
+}
+
+fn write_matches(
+    env: &Env,
+    db_handles: &DBHandles,
+    filter: &RecordFilter,
+    order: &SortOrder,
+    include_deleted: bool,
+    mut writer: RowWriter,
+) -> Result<(), ExportError> {
+    let rtxn = env.read_txn()?;
+
+    if let Some(descending) = order.key_order() {
+        for_each_match(&rtxn, db_handles, filter, descending, |key, record| writer.write(&key, &record, None))?;
+        if include_deleted && !writer.stopped {
+            for_each_deleted_match(&rtxn, db_handles, filter, descending, |key, tombstone| {
+                writer.write(&key, &tombstone.record, Some(tombstone.deleted_at))
+            })?;
+        }
+        return writer.finish();
+    }
+
+    let mut records = collect_matches(&rtxn, db_handles, filter)?;
+    order.sort(&mut records);
+    for (key, record) in records {
+        if writer.write(&key, &record, None).is_break() {
+            break;
+        }
+    }
+
+    if include_deleted && !writer.stopped {
+        let mut deleted_at = HashMap::new();
+        let mut records = vec![];
+        for (key, tombstone) in collect_deleted_matches(&rtxn, db_handles, filter)? {
+            deleted_at.insert(key.clone(), tombstone.deleted_at);
+            records.push((key, tombstone.record));
+        }
+        order.sort(&mut records);
+        for (key, record) in records {
+            if writer.write(&key, &record, deleted_at.get(&key).copied()).is_break() {
+                break;
+            }
+        }
+    }
+
+    writer.finish()
+}
+
+/// Streams every record matching `filter` as CSV or NDJSON, followed by the
+/// matching soft-deleted records when `include_deleted` is set. Rows are
+/// encoded straight off the LMDB cursor, forwards or backwards, on a
+/// blocking thread and handed to the response in chunks, so the listing is
+/// never held in memory. Any order other than key order has to collect and
+/// sort the matches first.
+pub fn stream_matches(
+    env: Arc<Env>,
+    db_handles: Arc<DBHandles>,
+    filter: RecordFilter,
+    order: SortOrder,
+    include_deleted: bool,
+    format: ExportFormat,
+    columns: Vec<String>,
+) -> HttpResponse {
+    let (mut sender, receiver) = mpsc::channel(4);
+
+    actix_web::rt::task::spawn_blocking(move || {
+        let result = RowWriter::new(format, &columns, &mut sender)
+            .and_then(|writer| write_matches(&env, &db_handles, &filter, &order, include_deleted, writer));
+
+        if let Err(e) = result {
+            eprintln!("Export failed: {e}");
+            let _ = send(&mut sender, Err(std::io::Error::other(e.to_string())));
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .streaming(receiver)
+}
@@ -1,10 +1,16 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use fake::{Fake, Faker};
 use futures::stream::{FuturesUnordered, StreamExt};
 use reqwest::Client;
 use serde_json::{json, Value};
-use crate::struct_definitions::{DBSchema, Payments, ProcessStatus, ProcessingStatusSchema, Status};
+use crate::address::normalize_address;
+use crate::counters::{count_record, count_records, uncount_record};
+use crate::search::{index_text, unindex_text};
+use crate::workflow::{unindex_assignee, unindex_current_state};
+use crate::audit::{record_change, AuditContext, Change, MAIN_DB, PAYMENTS_DB, PROCESSING_STATE_DB};
+use crate::struct_definitions::{BulkRecord, DBHandles, DBSchema, KeySchema, Payments, ProcessStatus, ProcessingStatusSchema, Status};
+use heed::{types::{DecodeIgnore, Str}, Database, RoTxn, RwTxn};
 use tokio::{sync::Semaphore, task};
 use rand::{seq::IndexedRandom, Rng};
 use std::time::Duration;
@@ -61,8 +67,10 @@ pub async fn loader() -> Result<(), Box<dyn std::error::Error>> {
                 status_updated: random_naive_datetime(),
                 county: COUNTY.choose(&mut rand::rng()).unwrap().to_string().clone(),
                 address: Faker.fake(),
+                normalized_address: String::new(),
                 county_status: ARR.choose(&mut rand::rng()).unwrap().clone(),
                 manual_status: ARR.choose(&mut rand::rng()).unwrap().clone(),
+                revision: 0,
             };
 
             let url = std::env::var("HOST_URL").expect("URL must be set");
@@ -97,7 +105,8 @@ pub async fn loader() -> Result<(), Box<dyn std::error::Error>> {
                     processing_status,
                     due_date,
                     assigned_to: assigned,
-                    last_modified
+                    last_modified,
+                    revision: 0
                 };
     
                 let url = std::env::var("HOST_URL").expect("URL must be set");
@@ -133,7 +142,8 @@ pub async fn loader() -> Result<(), Box<dyn std::error::Error>> {
                     amount,
                     date,
                     payment,
-                    status 
+                    status,
+                    revision: 0
                 };
     
                 let url = std::env::var("HOST_URL").expect("URL must be set");
@@ -164,7 +174,7 @@ pub async fn loader() -> Result<(), Box<dyn std::error::Error>> {
             drop(permit);
         }));
     }
-    while let Some(_) = tasks.next().await {}
+    while tasks.next().await.is_some() {}
     Ok(())
 }
 
@@ -178,13 +188,176 @@ pub fn data_with_response_time(duration: Duration, record: Vec<(String, DBSchema
     response
 }
 
-pub fn data_with_response_time_for_slice(duration: Duration, record: &[DBSchema], total_records: usize) -> Value {
-    let response = json!({
-        "Response_time": duration.as_micros(),
-        "Number_of_records": total_records,
-        "data": record
-    });
+pub fn index_record(db_handles: &DBHandles, wtxn: &mut RwTxn, key: &str, record: &DBSchema) -> heed::Result<()> {
+    let index_key = KeySchema::from(record);
+    let mut set = db_handles.composite_index.get(wtxn, &index_key)?.unwrap_or_default();
+    set.insert(key.to_owned());
+    db_handles.composite_index.put(wtxn, &index_key, &set)?;
 
-    response
+    db_handles.permit_index.put(wtxn, &record.permit_number, key)?;
+    index_text(db_handles, wtxn, key, record)?;
+    count_record(db_handles, wtxn, record)?;
+
+    Ok(())
+}
+
+/// Splits a `/bulk/records` body into items. A body starting with `[` is a
+/// JSON array, anything else is NDJSON with one item per non-blank line.
+/// Items that fail to parse are kept as errors so indices line up.
+pub fn parse_bulk_items(body: &[u8]) -> Result<Vec<Result<BulkRecord, String>>, String> {
+    let body = std::str::from_utf8(body).map_err(|e| format!("Body is not valid UTF-8: {e}"))?;
+
+    if body.trim_start().starts_with('[') {
+        let items: Vec<Value> = serde_json::from_str(body).map_err(|e| format!("Invalid JSON array: {e}"))?;
+        return Ok(items
+            .into_iter()
+            .map(|item| serde_json::from_value(item).map_err(|e| e.to_string()))
+            .collect());
+    }
+
+    Ok(body
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| e.to_string()))
+        .collect())
 }
 
+/// Stores a new record under a fresh `"{opened:?}-{uuid}"` key and audits it.
+/// Indexing is left to the caller so batches can go through [`index_records`].
+pub fn insert_new_record(
+    db_handles: &DBHandles,
+    wtxn: &mut RwTxn,
+    audit: &AuditContext,
+    mut record: DBSchema,
+) -> heed::Result<(String, DBSchema)> {
+    let key = format!("{:?}-{}", record.opened, uuid::Uuid::new_v4());
+    record.revision = 1;
+    record.normalized_address = normalize_address(&record.address);
+
+    db_handles.main_db.put(wtxn, &key, &record)?;
+    record_change(db_handles, wtxn, audit, Change::created(MAIN_DB, &key, &record))?;
+
+    Ok((key, record))
+}
+
+/// Indexes a batch of new records, writing each `composite_index` entry once
+/// however many records share it.
+pub fn index_records(db_handles: &DBHandles, wtxn: &mut RwTxn, records: &[(String, DBSchema)]) -> heed::Result<()> {
+    let mut keys_by_index: HashMap<KeySchema, Vec<&str>> = HashMap::new();
+    for (key, record) in records {
+        keys_by_index.entry(KeySchema::from(record)).or_default().push(key);
+        db_handles.permit_index.put(wtxn, &record.permit_number, key)?;
+        index_text(db_handles, wtxn, key, record)?;
+    }
+
+    for (index_key, keys) in keys_by_index {
+        let mut set = db_handles.composite_index.get(wtxn, &index_key)?.unwrap_or_default();
+        set.extend(keys.into_iter().map(str::to_owned));
+        db_handles.composite_index.put(wtxn, &index_key, &set)?;
+    }
+    count_records(db_handles, wtxn, records.iter().map(|(_, record)| record))?;
+
+    Ok(())
+}
+
+pub fn unindex_record(db_handles: &DBHandles, wtxn: &mut RwTxn, key: &str, record: &DBSchema) -> heed::Result<()> {
+    let index_key = KeySchema::from(record);
+    if let Some(mut set) = db_handles.composite_index.get(wtxn, &index_key)? {
+        set.remove(key);
+        if set.is_empty() {
+            db_handles.composite_index.delete(wtxn, &index_key)?;
+        } else {
+            db_handles.composite_index.put(wtxn, &index_key, &set)?;
+        }
+    }
+
+    let indexed = db_handles
+        .permit_index
+        .get(wtxn, &record.permit_number)?
+        .is_some_and(|indexed_key| indexed_key == key);
+    if indexed {
+        db_handles.permit_index.delete(wtxn, &record.permit_number)?;
+    }
+    unindex_text(db_handles, wtxn, key, record)?;
+    uncount_record(db_handles, wtxn, record)?;
+
+    Ok(())
+}
+
+/// Returns the key that already owns `permit_number`, ignoring `own_key` so
+/// a record can be saved again under its own number. Tombstones keep their
+/// number until purged, since their processing states and payments are
+/// still filed under it.
+pub fn permit_number_owner(
+    db_handles: &DBHandles,
+    rtxn: &RoTxn,
+    permit_number: &str,
+    own_key: Option<&str>,
+) -> heed::Result<Option<String>> {
+    if let Some(owner) = db_handles.permit_index.get(rtxn, permit_number)?
+        && Some(owner) != own_key
+    {
+        return Ok(Some(owner.to_string()));
+    }
+
+    if let Some(owner) = db_handles.deleted_permit_index.get(rtxn, permit_number)?
+        && Some(owner) != own_key
+    {
+        return Ok(Some(owner.to_string()));
+    }
+
+    Ok(None)
+}
+
+/// Processing states and payments are keyed by `{permit_number}-{date:?}`.
+/// Checking the suffix keeps `prefix_iter` on `"{permit_number}-"` from
+/// picking up rows of a permit whose number merely starts the same way.
+pub fn is_child_key(key: &str, permit_number: &str) -> bool {
+    key.strip_prefix(permit_number)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|date| NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f").is_ok())
+}
+
+/// Keys of the rows `db` holds for `permit_number`.
+pub fn child_keys<DC>(db: Database<Str, DC>, rtxn: &RoTxn, permit_number: &str) -> heed::Result<Vec<String>> {
+    let prefix = format!("{permit_number}-");
+    let mut keys = vec![];
+
+    for entry in db.remap_data_type::<DecodeIgnore>().prefix_iter(rtxn, &prefix)? {
+        let (key, _) = entry?;
+        if is_child_key(key, permit_number) {
+            keys.push(key.to_string());
+        }
+    }
+
+    Ok(keys)
+}
+
+/// Hard-deletes every processing state and payment of `permit_number`,
+/// auditing each row. Returns how many of each were removed.
+pub fn delete_child_rows(
+    db_handles: &DBHandles,
+    wtxn: &mut RwTxn,
+    audit: &AuditContext,
+    permit_number: &str,
+) -> heed::Result<(usize, usize)> {
+    let processing_keys = child_keys(db_handles.processing_state, wtxn, permit_number)?;
+    unindex_current_state(db_handles, wtxn, permit_number)?;
+    for key in &processing_keys {
+        if let Some(before) = db_handles.processing_state.get(wtxn, key)? {
+            unindex_assignee(db_handles, wtxn, key, &before)?;
+            db_handles.processing_state.delete(wtxn, key)?;
+            record_change(db_handles, wtxn, audit, Change::deleted(PROCESSING_STATE_DB, key, &before))?;
+        }
+    }
+
+    let payment_keys = child_keys(db_handles.payments_db, wtxn, permit_number)?;
+    for key in &payment_keys {
+        if let Some(before) = db_handles.payments_db.get(wtxn, key)? {
+            db_handles.payments_db.delete(wtxn, key)?;
+            record_change(db_handles, wtxn, audit, Change::deleted(PAYMENTS_DB, key, &before))?;
+        }
+    }
+
+    Ok((processing_keys.len(), payment_keys.len()))
+}
@@ -0,0 +1,187 @@
+use std::{collections::HashMap, str::FromStr};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use csv::StringRecord;
+
+use crate::{
+    address::normalize_address,
+    struct_definitions::{DBSchema, FilterError, RecordField, Status},
+};
+
+/// Query parameters of the form `map.<CSV header>=<field>` override the
+/// default header matching.
+pub const MAPPING_PREFIX: &str = "map.";
+
+const DATETIME_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%m/%d/%Y %H:%M:%S",
+    "%m/%d/%Y %H:%M",
+    "%m/%d/%Y %I:%M %p",
+];
+
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y/%m/%d", "%m/%d/%Y", "%m-%d-%Y", "%d-%b-%Y", "%b %d, %Y"];
+
+/// Which `DBSchema` field each CSV column feeds, if any.
+pub struct ColumnMapping {
+    columns: Vec<Option<RecordField>>,
+    pub ignored: Vec<String>,
+}
+
+fn normalize_header(header: &str) -> String {
+    header
+        .trim()
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+impl ColumnMapping {
+    /// Matches each header against an explicit `map.` parameter, then against
+    /// the field names themselves. Every field must end up with a column.
+    pub fn new(headers: &StringRecord, params: &HashMap<String, String>) -> Result<Self, FilterError> {
+        let mut columns = vec![];
+        let mut ignored = vec![];
+
+        for header in headers {
+            let explicit = params.get(&format!("{MAPPING_PREFIX}{header}"));
+            let field = match explicit {
+                Some(field) => Some(RecordField::from_str(field).map_err(|_| FilterError {
+                    code: "unknown_field",
+                    field: field.to_string(),
+                    message: format!("Column {header} is mapped to unknown field {field}"),
+                })?),
+                None => RecordField::from_str(&normalize_header(header)).ok(),
+            };
+
+            if let Some(field) = field
+                && columns.contains(&Some(field))
+            {
+                return Err(FilterError {
+                    code: "invalid_value",
+                    field: field.to_string(),
+                    message: format!("More than one column maps to {field}"),
+                });
+            }
+            if field.is_none() {
+                ignored.push(header.to_string());
+            }
+            columns.push(field);
+        }
+
+        if let Some(missing) = RecordField::ALL.iter().find(|field| !columns.contains(&Some(**field))) {
+            return Err(FilterError {
+                code: "invalid_value",
+                field: missing.to_string(),
+                message: format!("No column maps to {missing}"),
+            });
+        }
+
+        Ok(ColumnMapping { columns, ignored })
+    }
+
+    /// Builds a record from one CSV row, collecting every problem with it
+    /// rather than stopping at the first.
+    pub fn parse_row(&self, row: &StringRecord) -> Result<DBSchema, Vec<FilterError>> {
+        let values: HashMap<RecordField, &str> = self
+            .columns
+            .iter()
+            .zip(row.iter())
+            .filter_map(|(field, value)| field.map(|field| (field, value.trim())))
+            .collect();
+        let mut errors = vec![];
+
+        let mut text = |field: RecordField| match values.get(&field) {
+            Some(value) => value.to_string(),
+            None => {
+                errors.push(invalid(field, "Missing value".to_string()));
+                String::new()
+            }
+        };
+        let permit_link = text(RecordField::PermitLink);
+        let permit_number = text(RecordField::PermitNumber);
+        let client = text(RecordField::Client);
+        let county = text(RecordField::County);
+        let address = text(RecordField::Address);
+
+        if permit_number.is_empty() {
+            errors.push(invalid(RecordField::PermitNumber, "permit_number must not be empty".to_string()));
+        }
+
+        let mut date = |field: RecordField| {
+            let value = values.get(&field).copied().unwrap_or_default();
+            parse_import_date(value).ok_or_else(|| {
+                errors.push(invalid(field, format!("Unrecognised date {value}")));
+            })
+        };
+        let opened = date(RecordField::Opened);
+        let last_updated = date(RecordField::LastUpdated);
+        let status_updated = date(RecordField::StatusUpdated);
+
+        let mut status = |field: RecordField| {
+            let value = values.get(&field).copied().unwrap_or_default();
+            parse_import_status(value).ok_or_else(|| {
+                errors.push(invalid(field, format!("Unknown status {value}")));
+            })
+        };
+        let county_status = status(RecordField::CountyStatus);
+        let manual_status = status(RecordField::ManualStatus);
+
+        match (opened, last_updated, status_updated, county_status, manual_status) {
+            (Ok(opened), Ok(last_updated), Ok(status_updated), Ok(county_status), Ok(manual_status))
+                if errors.is_empty() =>
+            {
+                Ok(DBSchema {
+                    permit_link,
+                    permit_number,
+                    client,
+                    opened,
+                    last_updated,
+                    status_updated,
+                    county,
+                    county_status,
+                    manual_status,
+                    normalized_address: normalize_address(&address),
+                    address,
+                    revision: 0,
+                })
+            }
+            _ => Err(errors),
+        }
+    }
+}
+
+fn invalid(field: RecordField, message: String) -> FilterError {
+    FilterError {
+        code: "invalid_value",
+        field: field.to_string(),
+        message,
+    }
+}
+
+/// Accepts RFC 3339, ISO 8601 with or without the `T`, US-style dates and a
+/// few spelled-out month forms. Date-only values are taken as midnight.
+pub fn parse_import_date(value: &str) -> Option<NaiveDateTime> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.naive_utc());
+    }
+
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            DATE_FORMATS
+                .iter()
+                .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// Spreadsheets write "Under Review" or "under_review"; `Status::from_str`
+/// wants the words run together.
+pub fn parse_import_status(value: &str) -> Option<Status> {
+    value.replace([' ', '_', '-'], "").parse().ok()
+}
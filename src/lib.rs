@@ -1,5 +1,23 @@
-pub mod macros;
 pub mod struct_definitions;
+pub mod migrations;
 pub mod db_setup;
 pub mod helper_functions;
+pub mod address;
+pub mod validation;
+pub mod query_engine;
+pub mod api_error;
+pub mod audit;
+pub mod lifecycle;
+pub mod workflow;
+pub mod stages;
+pub mod concurrency;
+pub mod listing;
+pub mod pagination;
+pub mod search;
+pub mod counters;
+pub mod stats;
+pub mod export;
+pub mod import;
 pub mod endpoints;
+#[cfg(test)]
+pub mod test_support;
@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use heed::RwTxn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{
+    api_error::ApiError,
+    struct_definitions::{DBHandles, Status, StatusField, StatusTransition},
+};
+
+/// One move a status may make, and the guards it has to pass.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TransitionRule {
+    pub to: Status,
+    /// The update has to carry a `status_reason`.
+    #[serde(default)]
+    pub requires_reason: bool,
+    /// `status_updated` has to be at least this many days old.
+    #[serde(default)]
+    pub min_days_in_status: u64,
+}
+
+impl TransitionRule {
+    fn to(to: Status) -> Self {
+        TransitionRule {
+            to,
+            requires_reason: false,
+            min_days_in_status: 0,
+        }
+    }
+
+    fn with_reason(mut self) -> Self {
+        self.requires_reason = true;
+        self
+    }
+
+    /// When the `min_days_in_status` guard stops holding the move back, or
+    /// `None` if that is too far off to represent, i.e. never.
+    pub fn available_from(&self, status_updated: NaiveDateTime) -> Option<NaiveDateTime> {
+        i64::try_from(self.min_days_in_status)
+            .ok()
+            .and_then(chrono::TimeDelta::try_days)
+            .and_then(|wait| status_updated.checked_add_signed(wait))
+    }
+}
+
+/// The moves each `Status` may make, shared by `county_status` and
+/// `manual_status`. Staying in the same status is always allowed and is not
+/// a transition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransitionTable {
+    rules: BTreeMap<Status, Vec<TransitionRule>>,
+}
+
+impl Default for TransitionTable {
+    /// Permits move forward through review freely. Going back to an earlier
+    /// stage, or reopening a closed permit, needs a reason, and a closed
+    /// permit can only be reopened for review.
+    fn default() -> Self {
+        use Status::*;
+
+        let rules = BTreeMap::from([
+            (
+                Pending,
+                vec![
+                    TransitionRule::to(UnderReview),
+                    TransitionRule::to(Active),
+                    TransitionRule::to(Closed).with_reason(),
+                ],
+            ),
+            (
+                UnderReview,
+                vec![
+                    TransitionRule::to(Active),
+                    TransitionRule::to(Pending).with_reason(),
+                    TransitionRule::to(Inactive),
+                    TransitionRule::to(Closed),
+                ],
+            ),
+            (
+                Active,
+                vec![
+                    TransitionRule::to(Inactive),
+                    TransitionRule::to(UnderReview),
+                    TransitionRule::to(Closed),
+                ],
+            ),
+            (
+                Inactive,
+                vec![TransitionRule::to(Active).with_reason(), TransitionRule::to(Closed)],
+            ),
+            (Closed, vec![TransitionRule::to(UnderReview).with_reason()]),
+        ]);
+
+        TransitionTable { rules }
+    }
+}
+
+impl TransitionTable {
+    /// Reads a table written as `{"Pending": [{"to": "Active"}, ...]}`.
+    /// Statuses that are left out can't be moved out of.
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let rules: BTreeMap<Status, Vec<TransitionRule>> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+
+        for (from, targets) in &rules {
+            for (i, rule) in targets.iter().enumerate() {
+                if rule.to == *from {
+                    return Err(format!("{from} lists a transition to itself"));
+                }
+                if targets[..i].iter().any(|earlier| earlier.to == rule.to) {
+                    return Err(format!("{from} lists the transition to {} more than once", rule.to));
+                }
+            }
+        }
+
+        Ok(TransitionTable { rules })
+    }
+
+    pub fn allowed(&self, from: &Status) -> &[TransitionRule] {
+        self.rules.get(from).map_or(&[], Vec::as_slice)
+    }
+
+    /// Checks a move from `from` to `to` against the table and its guards.
+    /// Returns whether it is a real transition, i.e. the status changed.
+    pub fn check(
+        &self,
+        field: StatusField,
+        from: &Status,
+        to: &Status,
+        status_updated: NaiveDateTime,
+        now: NaiveDateTime,
+        reason: Option<&str>,
+    ) -> Result<bool, ApiError> {
+        if from == to {
+            return Ok(false);
+        }
+
+        let allowed = self.allowed(from);
+        let Some(rule) = allowed.iter().find(|rule| rule.to == *to) else {
+            let next: Vec<String> = allowed.iter().map(|rule| rule.to.to_string()).collect();
+            let message = if next.is_empty() {
+                format!("{field} cannot move from {from} to {to}, {from} is final")
+            } else {
+                format!("{field} cannot move from {from} to {to}, allowed next states are {}", next.join(", "))
+            };
+            return Err(ApiError::conflict(field.to_string(), message)
+                .with_detail("from", from.to_string())
+                .with_detail("to", to.to_string())
+                .with_detail("allowed", next));
+        };
+
+        if rule.requires_reason && reason.is_none_or(|reason| reason.trim().is_empty()) {
+            return Err(ApiError::validation(
+                "status_reason",
+                format!("Moving {field} from {from} to {to} requires a status_reason"),
+            ));
+        }
+
+        let available_from = rule.available_from(status_updated);
+        if available_from.is_none_or(|available_from| now < available_from) {
+            return Err(ApiError::conflict(
+                field.to_string(),
+                format!(
+                    "{field} has to stay {from} for {} days before moving to {to}",
+                    rule.min_days_in_status
+                ),
+            )
+            .with_detail("from", from.to_string())
+            .with_detail("to", to.to_string())
+            .with_detail("available_from", available_from.map(|available_from| format!("{available_from:?}"))));
+        }
+
+        Ok(true)
+    }
+
+    /// The allowed next states of one field, with whether each can be taken
+    /// right now.
+    pub fn next_states_json(&self, current: &Status, status_updated: NaiveDateTime, now: NaiveDateTime) -> Value {
+        let allowed: Vec<Value> = self
+            .allowed(current)
+            .iter()
+            .map(|rule| {
+                let available_from = rule.available_from(status_updated);
+                json!({
+                    "to": rule.to,
+                    "requires_reason": rule.requires_reason,
+                    "min_days_in_status": rule.min_days_in_status,
+                    "available": available_from.is_some_and(|available_from| now >= available_from),
+                    "available_from": available_from
+                })
+            })
+            .collect();
+
+        json!({
+            "current": current,
+            "since": status_updated,
+            "allowed": allowed
+        })
+    }
+}
+
+/// Appends transitions to the record's `status_history`.
+pub fn record_transitions(
+    db_handles: &DBHandles,
+    wtxn: &mut RwTxn,
+    key: &str,
+    transitions: Vec<StatusTransition>,
+) -> heed::Result<()> {
+    if transitions.is_empty() {
+        return Ok(());
+    }
+
+    let mut history = db_handles.status_history.get(wtxn, key)?.unwrap_or_default();
+    history.extend(transitions);
+    db_handles.status_history.put(wtxn, key, &history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::datetime;
+
+    #[test]
+    fn from_json_rejects_self_and_duplicate_transitions() {
+        assert!(TransitionTable::from_json(r#"{"Active": [{"to": "Active"}]}"#).is_err());
+        assert!(TransitionTable::from_json(r#"{"Active": [{"to": "Closed"}, {"to": "Closed"}]}"#).is_err());
+        assert!(TransitionTable::from_json(r#"{"Active": [{"to": "Nowhere"}]}"#).is_err());
+
+        let table = TransitionTable::from_json(r#"{"Active": [{"to": "Closed", "requires_reason": true}]}"#).unwrap();
+        assert_eq!(table.allowed(&Status::Active).len(), 1);
+        assert!(table.allowed(&Status::Closed).is_empty());
+    }
+
+    #[test]
+    fn check_enforces_the_table_and_its_guards() {
+        let table = TransitionTable::default();
+        let since = datetime("2026-01-01T00:00:00");
+        let now = datetime("2026-02-01T00:00:00");
+        let field = StatusField::CountyStatus;
+
+        assert_eq!(table.check(field, &Status::Active, &Status::Active, since, now, None).ok(), Some(false));
+        assert_eq!(table.check(field, &Status::Active, &Status::Inactive, since, now, None).ok(), Some(true));
+        assert!(table.check(field, &Status::Closed, &Status::Active, since, now, None).is_err());
+        assert!(table.check(field, &Status::Closed, &Status::UnderReview, since, now, None).is_err());
+        assert!(table.check(field, &Status::Closed, &Status::UnderReview, since, now, Some("  ")).is_err());
+        assert!(table.check(field, &Status::Closed, &Status::UnderReview, since, now, Some("appeal")).is_ok());
+    }
+
+    #[test]
+    fn min_days_in_status_holds_moves_back() {
+        let table = TransitionTable::from_json(r#"{"Active": [{"to": "Closed", "min_days_in_status": 30}]}"#).unwrap();
+        let since = datetime("2026-01-01T00:00:00");
+        let field = StatusField::CountyStatus;
+
+        assert!(table.check(field, &Status::Active, &Status::Closed, since, datetime("2026-01-30T23:59:59"), None).is_err());
+        assert!(table.check(field, &Status::Active, &Status::Closed, since, datetime("2026-01-31T00:00:00"), None).is_ok());
+    }
+
+    #[test]
+    fn min_days_too_large_to_represent_are_never_available() {
+        let table = TransitionTable::from_json(&format!(
+            r#"{{"Active": [{{"to": "Closed", "min_days_in_status": {}}}, {{"to": "Inactive", "min_days_in_status": 9000000000000}}]}}"#,
+            u64::MAX
+        ))
+        .unwrap();
+        let since = datetime("2026-01-01T00:00:00");
+        let now = datetime("2026-02-01T00:00:00");
+
+        for rule in table.allowed(&Status::Active) {
+            assert_eq!(rule.available_from(since), None);
+            assert!(table.check(StatusField::CountyStatus, &Status::Active, &rule.to, since, now, None).is_err());
+        }
+        assert_eq!(table.next_states_json(&Status::Active, since, now)["allowed"][0]["available"], false);
+    }
+}
@@ -0,0 +1,83 @@
+use actix_web::{
+    FromRequest, HttpRequest, HttpResponse, HttpResponseBuilder,
+    dev::Payload,
+    http::header::{self, ContentType},
+    web,
+};
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+
+use crate::{api_error::ApiError, struct_definitions::AppConfig};
+
+/// Parameters of a read endpoint, taken from the query string. Requests
+/// that still send them as a JSON body on GET are accepted while
+/// `ALLOW_GET_BODY` is on, and their responses carry a `Deprecation` header.
+pub struct ListingParams<T> {
+    pub params: T,
+    from_body: bool,
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for ListingParams<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if !req.query_string().is_empty() || !has_json_body(req) {
+            let params = web::Query::<T>::from_request(req, payload).into_inner();
+            return Box::pin(async move {
+                Ok(ListingParams {
+                    params: params?.into_inner(),
+                    from_body: false,
+                })
+            });
+        }
+
+        let allowed = req
+            .app_data::<web::Data<AppConfig>>()
+            .is_none_or(|config| config.allow_get_body);
+        if !allowed {
+            return Box::pin(async {
+                Err(ApiError::validation(
+                    "body",
+                    "Filters in a GET body are no longer accepted, send them as query parameters or POST /search",
+                )
+                .into())
+            });
+        }
+
+        let body = web::Json::<T>::from_request(req, payload);
+        Box::pin(async move {
+            Ok(ListingParams {
+                params: body.await?.into_inner(),
+                from_body: true,
+            })
+        })
+    }
+}
+
+impl<T> ListingParams<T> {
+    /// A 200 response, flagged as deprecated when the parameters came from
+    /// the body.
+    pub fn ok(&self) -> HttpResponseBuilder {
+        let mut response = HttpResponse::Ok();
+        if self.from_body {
+            response.insert_header(("Deprecation", "true"));
+            response.insert_header((header::LINK, "</search>; rel=\"alternate\""));
+        }
+        response
+    }
+}
+
+fn has_json_body(req: &HttpRequest) -> bool {
+    let is_json = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with(ContentType::json().0.essence_str()));
+    let is_empty = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .is_some_and(|length| length == "0");
+
+    is_json && !is_empty
+}
@@ -1,6 +0,0 @@
-#[macro_export]
-macro_rules! handle {
-    ($e: expr) => {
-        $e.map_err(|e| actix_web::error::ErrorInternalServerError(e))?
-    };
-}
\ No newline at end of file
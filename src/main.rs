@@ -1,6 +1,11 @@
-use actix_crud_api::endpoints::{create_payment, create_processing_state, create_record, delete_record, load_the_db, read_payment_details, read_permit_with_filter, read_processing_state, read_record, read_record_by_uuid, read_records_by_opened_date, update_payment_details, update_processing_status, update_records};
+use actix_crud_api::endpoints::{bulk_create_records, create_payment, create_processing_state, create_record, delete_record, export_permits_with_filter, export_records, export_records_by_opened_date, import_records, purge_deleted_records, read_audit_for_key, read_audit_log, read_duplicate_permits, read_similar_permits, read_permit_transitions, read_permit_stats, check_stats_counters, restore_record, load_the_db, read_payment_details, read_permit, read_permit_with_filter, read_processing_state, read_overdue_processing, read_due_processing, read_assignee_queue, read_workflow_stages, read_county_workflow_stages, update_workflow_stages, delete_workflow_stages, read_record, read_record_by_uuid, read_records_by_opened_date, search_records, text_search, update_payment_details, update_processing_status, update_records};
+use actix_crud_api::api_error::extractor_error;
 use actix_crud_api::struct_definitions::*;
 use actix_crud_api::db_setup::setup_db;
+use actix_crud_api::counters::{check_counters, rebuild_counters};
+use actix_crud_api::lifecycle::TransitionTable;
+use actix_crud_api::search::rebuild_search_index;
+use actix_crud_api::stages::load_stage_config;
 use actix_web::{App, HttpServer, web};
 use heed::EnvOpenOptions;
 use std::sync::Arc;
@@ -27,17 +32,123 @@ async fn main() -> std::io::Result<()> {
 
     println!("Environment Opened Successfully");
 
-    let db_handles = setup_db(env.clone())
-        .map_err(|e| {
-            actix_web::error::ErrorInternalServerError(format!("Error In Setting Up DataBase: {e}"))
-        })
-        .unwrap();
+    let db_handles = match setup_db(env.clone()) {
+        Ok(db_handles) => db_handles,
+        Err(e) => {
+            println!("Error In Setting Up DataBase: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    if let Ok(path) = std::env::var("WORKFLOW_STAGES") {
+        match std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| load_stage_config(&env, &db_handles, &path, &text))
+        {
+            Ok(counties) => println!("Seeded workflow stages for {counties} counties from {path}"),
+            Err(e) => {
+                println!("Could not load the WORKFLOW_STAGES config from {path}: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("reindex") {
+        match rebuild_search_index(&env, &db_handles) {
+            Ok(records) => println!("Rebuilt search_postings over {records} records"),
+            Err(e) => {
+                println!("Failed to rebuild search_postings: {e}");
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("check-counters") {
+        if std::env::args().nth(2).as_deref() == Some("--repair") {
+            match rebuild_counters(&env, &db_handles) {
+                Ok(counters) => println!("Rebuilt {counters} counters"),
+                Err(e) => {
+                    println!("Failed to rebuild counters: {e}");
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+
+        let drift = env
+            .read_txn()
+            .and_then(|rtxn| check_counters(&rtxn, &db_handles));
+        match drift {
+            Ok(drift) if drift.is_empty() => println!("Counters match main_db"),
+            Ok(drift) => {
+                for counter in &drift {
+                    println!(
+                        "{} / {} / {} / {}: stored {}, actual {}",
+                        counter.client, counter.county, counter.county_status, counter.month, counter.stored, counter.actual
+                    );
+                }
+                println!("{} counters have drifted, run check-counters --repair to rebuild them", drift.len());
+                std::process::exit(1);
+            }
+            Err(e) => {
+                println!("Failed to check counters: {e}");
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
 
     let db_handles = web::Data::new(DBdata {
         db_data: Arc::new(db_handles),
     });
 
     let db_state = web::Data::new(DbEnv { env });
+    let delete_policy = match std::env::var("DELETE_POLICY") {
+        Ok(policy) => policy.parse().unwrap_or_else(|_| {
+            println!("Unknown DELETE_POLICY {policy}, expected cascade, restrict or soft");
+            std::process::exit(1);
+        }),
+        Err(_) => DeletePolicy::Soft,
+    };
+    let tombstone_retention_days = match std::env::var("TOMBSTONE_RETENTION_DAYS") {
+        Ok(days) => days.parse().unwrap_or_else(|_| {
+            println!("TOMBSTONE_RETENTION_DAYS must be a whole number of days, got {days}");
+            std::process::exit(1);
+        }),
+        Err(_) => 30,
+    };
+    let bulk_payload_limit = match std::env::var("BULK_PAYLOAD_LIMIT") {
+        Ok(bytes) => bytes.parse().unwrap_or_else(|_| {
+            println!("BULK_PAYLOAD_LIMIT must be a number of bytes, got {bytes}");
+            std::process::exit(1);
+        }),
+        Err(_) => 64 * 1024 * 1024,
+    };
+    let allow_get_body = match std::env::var("ALLOW_GET_BODY") {
+        Ok(flag) => flag.parse().unwrap_or_else(|_| {
+            println!("ALLOW_GET_BODY must be true or false, got {flag}");
+            std::process::exit(1);
+        }),
+        Err(_) => true,
+    };
+    let transitions = match std::env::var("STATUS_TRANSITIONS") {
+        Ok(path) => std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| TransitionTable::from_json(&text))
+            .unwrap_or_else(|e| {
+                println!("Could not load the STATUS_TRANSITIONS table from {path}: {e}");
+                std::process::exit(1);
+            }),
+        Err(_) => TransitionTable::default(),
+    };
+    let config = web::Data::new(AppConfig {
+        delete_policy,
+        tombstone_retention_days,
+        bulk_payload_limit,
+        allow_get_body,
+        transitions,
+    });
     let url = std::env::var("URL").expect("URL must be set");
     let url = url.trim();
 
@@ -45,20 +156,49 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .app_data(db_state.clone())
             .app_data(db_handles.clone())
+            .app_data(config.clone())
+            .app_data(web::PayloadConfig::new(config.bulk_payload_limit))
+            .app_data(web::JsonConfig::default().error_handler(extractor_error("body")))
+            .app_data(web::QueryConfig::default().error_handler(extractor_error("query")))
+            .app_data(web::PathConfig::default().error_handler(extractor_error("path")))
             .service(create_record)
+            .service(bulk_create_records)
+            .service(import_records)
             .service(read_record_by_uuid)
             .service(update_records)
             .service(delete_record)
+            .service(restore_record)
+            .service(purge_deleted_records)
+            .service(read_audit_log)
+            .service(read_audit_for_key)
             .service(read_record)
             .service(load_the_db)
             .service(create_processing_state)
             .service(read_processing_state)
+            .service(read_overdue_processing)
+            .service(read_due_processing)
+            .service(read_assignee_queue)
+            .service(read_workflow_stages)
+            .service(read_county_workflow_stages)
+            .service(update_workflow_stages)
+            .service(delete_workflow_stages)
             .service(update_processing_status)
             .service(create_payment)
             .service(update_payment_details)
             .service(read_payment_details)
             .service(read_records_by_opened_date)
             .service(read_permit_with_filter)
+            .service(search_records)
+            .service(text_search)
+            .service(export_records)
+            .service(export_permits_with_filter)
+            .service(export_records_by_opened_date)
+            .service(read_duplicate_permits)
+            .service(read_similar_permits)
+            .service(read_permit_transitions)
+            .service(read_permit_stats)
+            .service(check_stats_counters)
+            .service(read_permit)
     })
     .bind(url)?
     .run()
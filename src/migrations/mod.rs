@@ -0,0 +1,356 @@
+use std::ops::Bound;
+
+use chrono::NaiveDateTime;
+use heed::{Env, RwTxn, types::*};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    address::normalize_address,
+    struct_definitions::{DBSchema, Payments, ProcessStatus, ProcessingStatusSchema, Status, Tombstone},
+};
+
+/// Version of the on-disk layout this binary reads and writes. Bump it and
+/// append a [`Migration`] to [`MIGRATIONS`] whenever a stored struct changes.
+pub const CURRENT_SCHEMA_VERSION: u32 = 4;
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+pub struct Migration {
+    /// The schema version the database is at once this migration has run.
+    pub version: u32,
+    pub description: &'static str,
+    pub run: fn(&Env, &mut RwTxn) -> heed::Result<()>,
+}
+
+/// Ordered by `version`. Version 1 is the layout that predates versioning,
+/// so there is nothing to run to reach it.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 2,
+        description: "add revision counters to records, processing states and payments",
+        run: add_revisions,
+    },
+    Migration {
+        version: 3,
+        description: "store the normalized address next to each record's address",
+        run: add_normalized_addresses,
+    },
+    Migration {
+        version: 4,
+        description: "normalize addresses again, abbreviating words only where they play their part",
+        run: renormalize_addresses,
+    },
+];
+
+#[derive(Deserialize, Serialize)]
+struct DBSchemaV1 {
+    permit_link: String,
+    permit_number: String,
+    client: String,
+    opened: NaiveDateTime,
+    last_updated: NaiveDateTime,
+    status_updated: NaiveDateTime,
+    county: String,
+    county_status: Status,
+    manual_status: Status,
+    address: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct DBSchemaV2 {
+    permit_link: String,
+    permit_number: String,
+    client: String,
+    opened: NaiveDateTime,
+    last_updated: NaiveDateTime,
+    status_updated: NaiveDateTime,
+    county: String,
+    county_status: Status,
+    manual_status: Status,
+    address: String,
+    revision: u64,
+}
+
+impl From<DBSchemaV1> for DBSchemaV2 {
+    fn from(old: DBSchemaV1) -> Self {
+        DBSchemaV2 {
+            permit_link: old.permit_link,
+            permit_number: old.permit_number,
+            client: old.client,
+            opened: old.opened,
+            last_updated: old.last_updated,
+            status_updated: old.status_updated,
+            county: old.county,
+            county_status: old.county_status,
+            manual_status: old.manual_status,
+            address: old.address,
+            revision: 1,
+        }
+    }
+}
+
+impl From<DBSchemaV2> for DBSchema {
+    fn from(old: DBSchemaV2) -> Self {
+        DBSchema {
+            normalized_address: normalize_address(&old.address),
+            permit_link: old.permit_link,
+            permit_number: old.permit_number,
+            client: old.client,
+            opened: old.opened,
+            last_updated: old.last_updated,
+            status_updated: old.status_updated,
+            county: old.county,
+            county_status: old.county_status,
+            manual_status: old.manual_status,
+            address: old.address,
+            revision: old.revision,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct TombstoneV1 {
+    record: DBSchemaV1,
+    deleted_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Serialize)]
+struct TombstoneV2 {
+    record: DBSchemaV2,
+    deleted_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ProcessingStatusSchemaV1 {
+    processing_status: ProcessStatus,
+    due_date: NaiveDateTime,
+    assigned_to: String,
+    last_modified: NaiveDateTime,
+}
+
+#[derive(Deserialize, Serialize)]
+struct PaymentsV1 {
+    payment: String,
+    date: NaiveDateTime,
+    amount: u64,
+    status: String,
+}
+
+fn add_revisions(env: &Env, wtxn: &mut RwTxn) -> heed::Result<()> {
+    rewrite_values::<DBSchemaV1, _>(env, wtxn, "main_db", DBSchemaV2::from)?;
+    rewrite_values(env, wtxn, "deleted_records", |old: TombstoneV1| TombstoneV2 {
+        record: old.record.into(),
+        deleted_at: old.deleted_at,
+    })?;
+    rewrite_values(env, wtxn, "processing_state_db", |old: ProcessingStatusSchemaV1| {
+        ProcessingStatusSchema {
+            processing_status: old.processing_status,
+            due_date: old.due_date,
+            assigned_to: old.assigned_to,
+            last_modified: old.last_modified,
+            revision: 1,
+        }
+    })?;
+    rewrite_values(env, wtxn, "payments_db", |old: PaymentsV1| Payments {
+        payment: old.payment,
+        date: old.date,
+        amount: old.amount,
+        status: old.status,
+        revision: 1,
+    })
+}
+
+fn add_normalized_addresses(env: &Env, wtxn: &mut RwTxn) -> heed::Result<()> {
+    rewrite_values::<DBSchemaV2, _>(env, wtxn, "main_db", DBSchema::from)?;
+    rewrite_values(env, wtxn, "deleted_records", |old: TombstoneV2| Tombstone {
+        record: old.record.into(),
+        deleted_at: old.deleted_at,
+    })
+}
+
+fn renormalize_addresses(env: &Env, wtxn: &mut RwTxn) -> heed::Result<()> {
+    rewrite_values(env, wtxn, "main_db", |mut record: DBSchema| {
+        record.normalized_address = normalize_address(&record.address);
+        record
+    })?;
+    rewrite_values(env, wtxn, "deleted_records", |mut tombstone: Tombstone| {
+        tombstone.record.normalized_address = normalize_address(&tombstone.record.address);
+        tombstone
+    })
+}
+
+/// Rows [`rewrite_values`] holds in memory at a time.
+const REWRITE_BATCH: usize = 1000;
+
+/// Decodes every value of the `name` database as `Old` and writes it back as
+/// `New`, a batch of rows at a time. Databases that don't exist yet are
+/// skipped.
+pub fn rewrite_values<Old, New>(
+    env: &Env,
+    wtxn: &mut RwTxn,
+    name: &str,
+    convert: impl Fn(Old) -> New,
+) -> heed::Result<()>
+where
+    Old: DeserializeOwned + 'static,
+    New: Serialize + 'static,
+{
+    let Some(db) = env.open_database::<Str, SerdeBincode<Old>>(wtxn, Some(name))? else {
+        return Ok(());
+    };
+
+    let new_db = db.remap_data_type::<SerdeBincode<New>>();
+
+    // Keys stay the same, so each batch resumes past the last key rewritten
+    // and never decodes a row that is already `New`.
+    let mut last: Option<String> = None;
+    loop {
+        let after = last.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        let mut rows = vec![];
+        for entry in db.range(wtxn, &(after, Bound::Unbounded))?.take(REWRITE_BATCH) {
+            let (key, value) = entry?;
+            rows.push((key.to_string(), convert(value)));
+        }
+
+        for (key, value) in &rows {
+            new_db.put(wtxn, key, value)?;
+        }
+        match rows.pop() {
+            Some((key, _)) if rows.len() + 1 == REWRITE_BATCH => last = Some(key),
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Brings the database up to [`CURRENT_SCHEMA_VERSION`] inside `wtxn`.
+/// Must run before anything decodes stored values with the current structs.
+pub fn run_migrations(env: &Env, wtxn: &mut RwTxn) -> Result<(), Box<dyn std::error::Error>> {
+    let fresh = env
+        .open_database::<Str, DecodeIgnore>(wtxn, Some("main_db"))?
+        .is_none();
+    let metadata = env.create_database::<Str, SerdeBincode<u32>>(wtxn, Some("metadata"))?;
+
+    let stored_version = match metadata.get(wtxn, SCHEMA_VERSION_KEY)? {
+        Some(version) => version,
+        None if fresh => CURRENT_SCHEMA_VERSION,
+        None => 1,
+    };
+
+    if stored_version > CURRENT_SCHEMA_VERSION {
+        return Err(format!(
+            "database schema version {stored_version} is newer than the version this binary supports ({CURRENT_SCHEMA_VERSION})"
+        )
+        .into());
+    }
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > stored_version)
+    {
+        println!(
+            "Migrating database to schema version {}: {}",
+            migration.version, migration.description
+        );
+        (migration.run)(env, wtxn)?;
+    }
+
+    metadata.put(wtxn, SCHEMA_VERSION_KEY, &CURRENT_SCHEMA_VERSION)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempEnv, datetime};
+
+    fn v1_record(permit_number: &str, address: &str) -> DBSchemaV1 {
+        DBSchemaV1 {
+            permit_link: format!("http://permits/{permit_number}"),
+            permit_number: permit_number.to_string(),
+            client: "acme".to_string(),
+            opened: datetime("2024-01-01T00:00:00"),
+            last_updated: datetime("2024-01-02T00:00:00"),
+            status_updated: datetime("2024-01-03T00:00:00"),
+            county: "king".to_string(),
+            county_status: Status::Active,
+            manual_status: Status::Pending,
+            address: address.to_string(),
+        }
+    }
+
+    #[test]
+    fn version_1_rows_are_rewritten_to_the_current_layout() {
+        let temp = TempEnv::open();
+        let mut wtxn = temp.env.write_txn().unwrap();
+        let main_db = temp.env.create_database::<Str, SerdeBincode<DBSchemaV1>>(&mut wtxn, Some("main_db")).unwrap();
+        // One more row than a batch, so the rewrite has to resume.
+        for at in 0..=REWRITE_BATCH {
+            main_db.put(&mut wtxn, &format!("key-{at:05}"), &v1_record(&format!("P{at}"), "1 Court Street")).unwrap();
+        }
+        let deleted_db = temp.env.create_database::<Str, SerdeBincode<TombstoneV1>>(&mut wtxn, Some("deleted_records")).unwrap();
+        let tombstone = TombstoneV1 {
+            record: v1_record("D1", "9 North Elm Avenue, Apartment 4"),
+            deleted_at: datetime("2024-02-01T00:00:00"),
+        };
+        deleted_db.put(&mut wtxn, "deleted-key", &tombstone).unwrap();
+        let processing_db = temp
+            .env
+            .create_database::<Str, SerdeBincode<ProcessingStatusSchemaV1>>(&mut wtxn, Some("processing_state_db"))
+            .unwrap();
+        let state = ProcessingStatusSchemaV1 {
+            processing_status: ProcessStatus::RevisionsReceived,
+            due_date: datetime("2024-03-01T00:00:00"),
+            assigned_to: "alice".to_string(),
+            last_modified: datetime("2024-01-05T00:00:00"),
+        };
+        processing_db.put(&mut wtxn, "P1-2024-01-05T00:00:00", &state).unwrap();
+        let payments_db = temp.env.create_database::<Str, SerdeBincode<PaymentsV1>>(&mut wtxn, Some("payments_db")).unwrap();
+        let payment = PaymentsV1 {
+            payment: "fee".to_string(),
+            date: datetime("2024-01-06T00:00:00"),
+            amount: 250,
+            status: "Paid".to_string(),
+        };
+        payments_db.put(&mut wtxn, "P1-2024-01-06T00:00:00", &payment).unwrap();
+        wtxn.commit().unwrap();
+
+        let db_handles = temp.set_up();
+        let rtxn = temp.env.read_txn().unwrap();
+
+        assert_eq!(db_handles.main_db.len(&rtxn).unwrap(), REWRITE_BATCH as u64 + 1);
+        for entry in db_handles.main_db.iter(&rtxn).unwrap() {
+            let (_, record) = entry.unwrap();
+            assert_eq!((record.revision, record.normalized_address.as_str()), (1, "1 COURT ST"));
+        }
+        let last = db_handles.main_db.get(&rtxn, &format!("key-{REWRITE_BATCH:05}")).unwrap().unwrap();
+        assert_eq!(last.permit_number, format!("P{REWRITE_BATCH}"));
+        assert_eq!(last.manual_status, Status::Pending);
+
+        let tombstone = db_handles.deleted_db.get(&rtxn, "deleted-key").unwrap().unwrap();
+        assert_eq!(tombstone.deleted_at, datetime("2024-02-01T00:00:00"));
+        assert_eq!(tombstone.record.normalized_address, "9 N ELM AVE APT 4");
+
+        let state = db_handles.processing_state.get(&rtxn, "P1-2024-01-05T00:00:00").unwrap().unwrap();
+        assert_eq!((state.processing_status, state.revision), (ProcessStatus::RevisionsReceived, 1));
+        let payment = db_handles.payments_db.get(&rtxn, "P1-2024-01-06T00:00:00").unwrap().unwrap();
+        assert_eq!((payment.amount, payment.revision), (250, 1));
+
+        let metadata = temp.env.open_database::<Str, SerdeBincode<u32>>(&rtxn, Some("metadata")).unwrap().unwrap();
+        assert_eq!(metadata.get(&rtxn, SCHEMA_VERSION_KEY).unwrap(), Some(CURRENT_SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn a_newer_schema_version_is_refused() {
+        let temp = TempEnv::open();
+        let mut wtxn = temp.env.write_txn().unwrap();
+        temp.env.create_database::<Str, SerdeBincode<DBSchema>>(&mut wtxn, Some("main_db")).unwrap();
+        let metadata = temp.env.create_database::<Str, SerdeBincode<u32>>(&mut wtxn, Some("metadata")).unwrap();
+        metadata.put(&mut wtxn, SCHEMA_VERSION_KEY, &(CURRENT_SCHEMA_VERSION + 1)).unwrap();
+        wtxn.commit().unwrap();
+
+        let mut wtxn = temp.env.write_txn().unwrap();
+        let error = run_migrations(&temp.env, &mut wtxn).unwrap_err();
+        assert!(error.to_string().contains("newer than the version this binary supports"), "{error}");
+    }
+}
@@ -0,0 +1,509 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Write},
+    ops::ControlFlow,
+    str::FromStr,
+};
+
+use chrono::NaiveDateTime;
+use heed::RoTxn;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    api_error::ApiError,
+    query_engine::{FieldRef, field_ref, for_each_match, for_each_match_after},
+    stats::count_permits,
+    struct_definitions::{DBHandles, DBSchema, RecordField, RecordFilter, Status},
+};
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+enum SortValue {
+    Text(String),
+    Status(Status),
+    Date(NaiveDateTime),
+}
+
+impl SortValue {
+    fn of(record: &DBSchema, field: RecordField) -> SortValue {
+        match field_ref(record, field) {
+            FieldRef::Text(text) => SortValue::Text(text.to_string()),
+            FieldRef::Status(status) => SortValue::Status(status.clone()),
+            FieldRef::Date(date) => SortValue::Date(date),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: RecordField,
+    pub descending: bool,
+}
+
+/// The order of a listing. Records are compared key by key, and whatever is
+/// still tied is ordered by `main_db` key in the direction of the first sort
+/// key. No keys at all is plain `main_db` key order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SortOrder {
+    keys: Vec<SortKey>,
+}
+
+impl SortOrder {
+    /// Reads `sort=field:asc,field:desc` over any record field. The older
+    /// `sort_key=field&sort=asc|dsc` pair is still understood.
+    pub fn parse(sort: Option<&str>, sort_key: Option<&str>) -> Result<SortOrder, ApiError> {
+        let sort = sort.map(str::trim).filter(|sort| !sort.is_empty());
+        let sort_key = sort_key.map(str::trim).filter(|sort_key| !sort_key.is_empty());
+
+        let legacy_direction = match sort {
+            None => sort_key.map(|_| false),
+            Some("asc") => Some(false),
+            Some("dsc" | "desc") => Some(true),
+            Some(_) => None,
+        };
+        if let Some(descending) = legacy_direction {
+            let field = parse_sort_field("sort_key", sort_key.unwrap_or("opened"))?;
+            return Ok(SortOrder {
+                keys: vec![SortKey { field, descending }],
+            });
+        }
+        let Some(list) = sort else {
+            return Ok(SortOrder::default());
+        };
+        if sort_key.is_some() {
+            return Err(ApiError::validation(
+                "sort_key",
+                "sort_key only goes with sort=asc or sort=dsc, put the field in the sort list instead",
+            ));
+        }
+
+        let mut keys: Vec<SortKey> = vec![];
+        for item in list.split(',').map(str::trim) {
+            let (name, direction) = item.split_once(':').unwrap_or((item, "asc"));
+            let field = parse_sort_field("sort", name.trim())?;
+            let descending = match direction.trim() {
+                "asc" => false,
+                "desc" | "dsc" => true,
+                other => {
+                    return Err(ApiError::validation(
+                        "sort",
+                        format!("'{other}' is not a sort direction, expected asc or desc"),
+                    ));
+                }
+            };
+            if keys.iter().any(|key| key.field == field) {
+                return Err(ApiError::validation("sort", format!("{field} appears more than once in sort")));
+            }
+            keys.push(SortKey { field, descending });
+        }
+
+        Ok(SortOrder { keys })
+    }
+
+    /// `Some(descending)` when this is `main_db` key order, which `opened`
+    /// on its own also is, since keys start with the opened date.
+    pub fn key_order(&self) -> Option<bool> {
+        match self.keys.as_slice() {
+            [] => Some(false),
+            [SortKey { field: RecordField::Opened, descending }] => Some(*descending),
+            _ => None,
+        }
+    }
+
+    fn values(&self, record: &DBSchema) -> Vec<SortValue> {
+        self.keys.iter().map(|key| SortValue::of(record, key.field)).collect()
+    }
+
+    fn compare(&self, a: (&[SortValue], &str), b: (&[SortValue], &str)) -> Ordering {
+        for (index, key) in self.keys.iter().enumerate() {
+            let ordering = match (a.0.get(index), b.0.get(index)) {
+                (Some(x), Some(y)) => x.cmp(y),
+                _ => Ordering::Equal,
+            };
+            let ordering = if key.descending { ordering.reverse() } else { ordering };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        match self.keys.first().is_some_and(|key| key.descending) {
+            true => b.1.cmp(a.1),
+            false => a.1.cmp(b.1),
+        }
+    }
+
+    /// Sorts records in place. Key order is left as it is, since that is
+    /// how the query engine hands records over.
+    pub fn sort(&self, records: &mut Vec<(String, DBSchema)>) {
+        match self.key_order() {
+            Some(false) => {}
+            Some(true) => records.reverse(),
+            None => {
+                let mut keyed: Vec<(Vec<SortValue>, (String, DBSchema))> = records
+                    .drain(..)
+                    .map(|(key, record)| (self.values(&record), (key, record)))
+                    .collect();
+                keyed.sort_by(|a, b| self.compare((&a.0, &a.1.0), (&b.0, &b.1.0)));
+                records.extend(keyed.into_iter().map(|(_, record)| record));
+            }
+        }
+    }
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, key) in self.keys.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            let direction = if key.descending { "desc" } else { "asc" };
+            write!(f, "{separator}{}:{direction}", key.field)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_sort_field(param: &str, name: &str) -> Result<RecordField, ApiError> {
+    RecordField::from_str(name).map_err(|_| ApiError::Validation {
+        code: "unknown_field",
+        field: Some(param.to_string()),
+        message: format!("Cannot sort by {name}, it is not a field of a permit record"),
+    })
+}
+
+/// Where a listing stopped: the last record's key and sort values, plus the
+/// order it was read in so a token can't be replayed against another sort.
+/// Clients only ever see it hex encoded.
+#[derive(Debug, Serialize, Deserialize)]
+struct PageCursor {
+    key: String,
+    order: String,
+    values: Vec<SortValue>,
+}
+
+impl PageCursor {
+    fn after(order: &SortOrder, key: &str, record: &DBSchema) -> PageCursor {
+        PageCursor {
+            key: key.to_string(),
+            order: order.to_string(),
+            values: order.values(record),
+        }
+    }
+
+    fn encode(&self) -> String {
+        encode_token(self)
+    }
+
+    fn decode(token: &str, order: &SortOrder) -> Result<PageCursor, ApiError> {
+        let cursor: PageCursor = decode_token(token)?;
+
+        if cursor.order != order.to_string() {
+            return Err(ApiError::validation(
+                "cursor",
+                "The cursor was issued for a different sort order",
+            ));
+        }
+
+        Ok(cursor)
+    }
+}
+
+/// Hex encoded JSON, so that cursors are opaque and URL safe.
+pub fn encode_token<T: Serialize>(value: &T) -> String {
+    let json = serde_json::to_vec(value).unwrap_or_default();
+    json.iter().fold(String::with_capacity(json.len() * 2), |mut token, byte| {
+        let _ = write!(token, "{byte:02x}");
+        token
+    })
+}
+
+/// Reads back an [`encode_token`] cursor.
+pub fn decode_token<T: DeserializeOwned>(token: &str) -> Result<T, ApiError> {
+    let malformed = || ApiError::validation("cursor", "Malformed cursor, pass back the next_cursor of an earlier page");
+
+    let bytes = (0..token.len())
+        .step_by(2)
+        .map(|at| token.get(at..at + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(malformed)?;
+
+    serde_json::from_slice(&bytes).map_err(|_| malformed())
+}
+
+/// Which slice of a listing to return. `cursor` resumes after an earlier
+/// page, `page` skips whole pages from there (1 based) and `limit: None`
+/// returns everything that is left.
+pub struct PageRequest<'a> {
+    pub cursor: Option<&'a str>,
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+impl<'a> PageRequest<'a> {
+    /// `records_per_page` as given, or [`DEFAULT_PAGE_SIZE`] as soon as the
+    /// client pages at all. Without any paging parameters the whole listing
+    /// comes back.
+    pub fn on_request(cursor: Option<&'a str>, page: Option<usize>, records_per_page: Option<usize>) -> PageRequest<'a> {
+        let paged = cursor.is_some() || page.is_some();
+        PageRequest {
+            cursor,
+            page,
+            limit: records_per_page.or(paged.then_some(DEFAULT_PAGE_SIZE)),
+        }
+    }
+
+    /// Rows to skip past the cursor, or past the start without one. `total`
+    /// is only checked without a cursor, since rows may have been deleted
+    /// since the cursor was handed out.
+    fn skip(&self, total: usize) -> Result<usize, ApiError> {
+        if self.limit == Some(0) {
+            return Err(ApiError::validation("records_per_page", "records_per_page starts at 1"));
+        }
+        let Some(page) = self.page else {
+            return Ok(0);
+        };
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+
+        if page == 0 {
+            return Err(ApiError::validation("page", "page and records_per_page start at 1"));
+        }
+        let skip = (page - 1).saturating_mul(limit);
+        if self.cursor.is_none() && page > 1 && skip >= total {
+            return Err(ApiError::validation(
+                "page",
+                format!("The DB only has {} Pages", total.div_ceil(limit)),
+            ));
+        }
+
+        Ok(skip)
+    }
+}
+
+pub struct Page {
+    pub records: Vec<(String, DBSchema)>,
+    pub next_cursor: Option<String>,
+    /// Every record the listing covers, not just this page.
+    pub total: usize,
+}
+
+impl Page {
+    /// Cuts a batch read with one record of lookahead down to `limit`,
+    /// issuing a cursor when that extra record shows there is more.
+    fn from_batch(mut records: Vec<(String, DBSchema)>, order: &SortOrder, limit: Option<usize>, total: usize) -> Page {
+        let next_cursor = match limit {
+            Some(limit) if records.len() > limit => {
+                records.truncate(limit);
+                records.last().map(|(key, record)| PageCursor::after(order, key, record).encode())
+            }
+            _ => None,
+        };
+
+        Page {
+            records,
+            next_cursor,
+            total,
+        }
+    }
+}
+
+/// A page of the records matching `filter`, which may be empty. In key
+/// order this resumes straight from the cursor with a range read, walking
+/// backwards for descending order. Other orders still have to look at every
+/// match, but only hold on to the ones that can land on the page.
+pub fn page_of_matches(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    filter: &RecordFilter,
+    order: &SortOrder,
+    request: &PageRequest,
+) -> Result<Page, ApiError> {
+    let cursor = request.cursor.map(|token| PageCursor::decode(token, order)).transpose()?;
+    let total = match filter.is_empty() {
+        true => db_handles.main_db.len(rtxn)? as usize,
+        false => count_permits(rtxn, db_handles, filter, &[])?.total() as usize,
+    };
+    let skip = request.skip(total)?;
+    let take = request.limit.map(|limit| limit.saturating_add(1));
+
+    if let Some(descending) = order.key_order() {
+        let after = cursor.as_ref().map(|cursor| cursor.key.as_str());
+        let (mut skipped, mut batch) = (0, vec![]);
+        for_each_match_after(rtxn, db_handles, filter, descending, after, |key, record| {
+            if skipped < skip {
+                skipped += 1;
+                return ControlFlow::Continue(());
+            }
+            batch.push((key, record));
+            match take.is_some_and(|take| batch.len() >= take) {
+                true => ControlFlow::Break(()),
+                false => ControlFlow::Continue(()),
+            }
+        })?;
+        return Ok(Page::from_batch(batch, order, request.limit, total));
+    }
+
+    // The page is the first `keep` matches past the cursor, so the rest are
+    // dropped whenever twice that many have piled up.
+    let keep = take.map(|take| take.saturating_add(skip));
+    let by_order = |a: &(Vec<SortValue>, String, DBSchema), b: &(Vec<SortValue>, String, DBSchema)| {
+        order.compare((&a.0, &a.1), (&b.0, &b.1))
+    };
+    let mut kept: Vec<(Vec<SortValue>, String, DBSchema)> = vec![];
+    for_each_match(rtxn, db_handles, filter, false, |key, record| {
+        let values = order.values(&record);
+        if let Some(cursor) = &cursor
+            && order.compare((&values, &key), (&cursor.values, &cursor.key)) != Ordering::Greater
+        {
+            return ControlFlow::Continue(());
+        }
+        kept.push((values, key, record));
+        if let Some(keep) = keep
+            && kept.len() >= keep.saturating_mul(2)
+        {
+            kept.select_nth_unstable_by(keep, by_order);
+            kept.truncate(keep);
+        }
+        ControlFlow::Continue(())
+    })?;
+    kept.sort_by(by_order);
+
+    let batch = kept
+        .into_iter()
+        .skip(skip)
+        .take(take.unwrap_or(usize::MAX))
+        .map(|(_, key, record)| (key, record))
+        .collect();
+
+    Ok(Page::from_batch(batch, order, request.limit, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempEnv, datetime, record};
+
+    #[test]
+    fn cursor_round_trips() {
+        let order = SortOrder::parse(Some("client:desc,opened:asc"), None).unwrap();
+        let record = record("P1", "acme", datetime("2024-01-05T10:00:00.500"));
+        let cursor = PageCursor::after(&order, "2024-01-05T10:00:00.500-P1", &record);
+
+        let token = cursor.encode();
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+
+        let decoded = PageCursor::decode(&token, &order).unwrap();
+        assert_eq!(decoded.key, cursor.key);
+        assert_eq!(decoded.order, "client:desc,opened:asc");
+        assert_eq!(decoded.values, cursor.values);
+    }
+
+    #[test]
+    fn cursor_is_tied_to_its_sort_order() {
+        let order = SortOrder::parse(Some("client:asc"), None).unwrap();
+        let other = SortOrder::parse(Some("client:desc"), None).unwrap();
+        let token = PageCursor::after(&order, "key", &record("P1", "acme", datetime("2024-01-05T10:00:00"))).encode();
+
+        assert!(PageCursor::decode(&token, &other).is_err());
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        let order = SortOrder::default();
+        for token in ["", "abc", "zz", "7b7d", "not a cursor"] {
+            assert!(PageCursor::decode(token, &order).is_err(), "{token:?}");
+        }
+    }
+
+    #[test]
+    fn legacy_sort_parameters_still_parse() {
+        assert_eq!(SortOrder::parse(Some("dsc"), None).unwrap().key_order(), Some(true));
+        assert_eq!(SortOrder::parse(None, Some("opened")).unwrap().key_order(), Some(false));
+        assert_eq!(SortOrder::parse(Some("asc"), Some("client")).unwrap().to_string(), "client:asc");
+        assert!(SortOrder::parse(Some("client:asc"), Some("client")).is_err());
+        assert!(SortOrder::parse(Some("client:up"), None).is_err());
+        assert!(SortOrder::parse(Some("client,client:desc"), None).is_err());
+    }
+
+    /// Follows `next_cursor` from the first page to the last, returning the
+    /// permit numbers in the order they came back.
+    fn read_all_pages(temp: &TempEnv, db_handles: &DBHandles, filter: &RecordFilter, order: &SortOrder) -> Vec<String> {
+        let rtxn = temp.env.read_txn().unwrap();
+        let mut seen = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let request = PageRequest {
+                cursor: cursor.as_deref(),
+                page: None,
+                limit: Some(2),
+            };
+            let page = page_of_matches(&rtxn, db_handles, filter, order, &request).unwrap();
+            seen.extend(page.records.into_iter().map(|(_, record)| record.permit_number));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return seen,
+            }
+        }
+    }
+
+    fn five_records(temp: &TempEnv, db_handles: &DBHandles) {
+        temp.insert(
+            db_handles,
+            &[
+                record("P1", "acme", datetime("2024-01-01T00:00:00")),
+                record("P2", "bolt", datetime("2024-01-02T00:00:00")),
+                record("P3", "acme", datetime("2024-01-03T00:00:00")),
+                record("P4", "bolt", datetime("2024-01-04T00:00:00")),
+                record("P5", "acme", datetime("2024-01-05T00:00:00")),
+            ],
+        );
+    }
+
+    #[test]
+    fn cursor_pages_cover_every_match_once() {
+        let temp = TempEnv::open();
+        let db_handles = temp.set_up();
+        five_records(&temp, &db_handles);
+        let everything = RecordFilter::default();
+
+        let order = SortOrder::parse(Some("client:desc"), None).unwrap();
+        // Ties on client fall back to key order, descending like the first key.
+        assert_eq!(read_all_pages(&temp, &db_handles, &everything, &order), ["P4", "P2", "P5", "P3", "P1"]);
+
+        let order = SortOrder::parse(Some("opened:desc"), None).unwrap();
+        assert_eq!(read_all_pages(&temp, &db_handles, &everything, &order), ["P5", "P4", "P3", "P2", "P1"]);
+    }
+
+    #[test]
+    fn filtered_pages_resume_from_the_cursor() {
+        let temp = TempEnv::open();
+        let db_handles = temp.set_up();
+        five_records(&temp, &db_handles);
+        let params = [("client".to_string(), "acme".to_string())].into_iter().collect();
+        let acme = RecordFilter::parse(&params, &[]).unwrap();
+
+        assert_eq!(read_all_pages(&temp, &db_handles, &acme, &SortOrder::default()), ["P1", "P3", "P5"]);
+        let order = SortOrder::parse(Some("permit_number:desc"), None).unwrap();
+        assert_eq!(read_all_pages(&temp, &db_handles, &acme, &order), ["P5", "P3", "P1"]);
+
+        let rtxn = temp.env.read_txn().unwrap();
+        // Asking for a page pages by the default size, so there is no second page.
+        let page = page_of_matches(&rtxn, &db_handles, &acme, &order, &PageRequest::on_request(None, Some(1), None)).unwrap();
+        assert_eq!((page.records.len(), page.total, page.next_cursor), (3, 3, None));
+        assert!(page_of_matches(&rtxn, &db_handles, &acme, &order, &PageRequest::on_request(None, Some(2), None)).is_err());
+    }
+
+    #[test]
+    fn page_requests_validate_page_and_limit() {
+        let request = |cursor, page, limit| PageRequest { cursor, page, limit };
+
+        assert_eq!(request(None, Some(2), Some(10)).skip(25).unwrap(), 10);
+        assert!(request(None, Some(4), Some(10)).skip(25).is_err());
+        assert!(request(None, Some(0), Some(10)).skip(25).is_err());
+        assert!(request(None, None, Some(0)).skip(25).is_err());
+        // A cursor's page is counted from the cursor, whatever is left.
+        assert_eq!(request(Some("token"), Some(4), Some(10)).skip(0).unwrap(), 30);
+
+        assert_eq!(PageRequest::on_request(None, Some(2), None).limit, Some(DEFAULT_PAGE_SIZE));
+        assert_eq!(PageRequest::on_request(Some("token"), None, None).limit, Some(DEFAULT_PAGE_SIZE));
+        assert_eq!(PageRequest::on_request(None, None, None).limit, None);
+    }
+}
@@ -0,0 +1,676 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    ops::{Bound, ControlFlow, RangeBounds},
+    str::FromStr,
+};
+
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime};
+use heed::{RoTxn, types::DecodeIgnore};
+
+use crate::{
+    struct_definitions::{
+        DBHandles, DBSchema, FieldFilter, FilterError, FilterValue, KeySchema, Predicate,
+        RecordField, RecordFilter, Status, Tombstone,
+    },
+    validation::{ParsedDate, date_error_message, parse_date, relative_range, relative_range_error_message},
+};
+
+pub enum FieldRef<'a> {
+    Text(&'a str),
+    Status(&'a Status),
+    Date(NaiveDateTime),
+}
+
+pub fn field_ref(record: &DBSchema, field: RecordField) -> FieldRef<'_> {
+    match field {
+        RecordField::PermitLink => FieldRef::Text(&record.permit_link),
+        RecordField::PermitNumber => FieldRef::Text(&record.permit_number),
+        RecordField::Client => FieldRef::Text(&record.client),
+        RecordField::County => FieldRef::Text(&record.county),
+        RecordField::Address => FieldRef::Text(&record.address),
+        RecordField::Opened => FieldRef::Date(record.opened),
+        RecordField::LastUpdated => FieldRef::Date(record.last_updated),
+        RecordField::StatusUpdated => FieldRef::Date(record.status_updated),
+        RecordField::CountyStatus => FieldRef::Status(&record.county_status),
+        RecordField::ManualStatus => FieldRef::Status(&record.manual_status),
+    }
+}
+
+fn start_of_day(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(0, 0, 0).unwrap()
+}
+
+fn next_day(date: NaiveDate) -> NaiveDateTime {
+    start_of_day(date.checked_add_days(Days::new(1)).unwrap_or(date))
+}
+
+fn invalid_value(field: &str, message: String) -> FilterError {
+    FilterError {
+        code: "invalid_value",
+        field: field.to_string(),
+        message,
+    }
+}
+
+fn parse_value(name: &str, field: RecordField, value: &str) -> Result<FilterValue, FilterError> {
+    if field.is_status() {
+        return Status::from_str(value)
+            .map(FilterValue::Status)
+            .map_err(|_| invalid_value(name, format!("'{value}' is not a valid status")));
+    }
+
+    if field.is_date() {
+        return match parse_date(value) {
+            Some(ParsedDate::Day(date)) => Ok(FilterValue::Day(date)),
+            Some(ParsedDate::Instant(date)) => Ok(FilterValue::Date(date)),
+            None => Err(invalid_value(name, date_error_message(value))),
+        };
+    }
+
+    Ok(FilterValue::Text(value.to_string()))
+}
+
+fn parse_range(name: &str, op: &str, value: &str) -> Result<Predicate, FilterError> {
+    if op == "range" {
+        let today = chrono::Utc::now().date_naive();
+        let (first, last) = relative_range(value, today)
+            .ok_or_else(|| invalid_value(name, relative_range_error_message(value)))?;
+        return Ok(Predicate::Range(
+            Bound::Included(start_of_day(first)),
+            Bound::Excluded(next_day(last)),
+        ));
+    }
+
+    let date = parse_date(value).ok_or_else(|| invalid_value(name, date_error_message(value)))?;
+
+    let predicate = match (op, date) {
+        ("gte", ParsedDate::Day(day)) => Predicate::Range(Bound::Included(start_of_day(day)), Bound::Unbounded),
+        ("gt", ParsedDate::Day(day)) => Predicate::Range(Bound::Included(next_day(day)), Bound::Unbounded),
+        ("lte", ParsedDate::Day(day)) => Predicate::Range(Bound::Unbounded, Bound::Excluded(next_day(day))),
+        ("lt", ParsedDate::Day(day)) => Predicate::Range(Bound::Unbounded, Bound::Excluded(start_of_day(day))),
+        ("gte", ParsedDate::Instant(date)) => Predicate::Range(Bound::Included(date), Bound::Unbounded),
+        ("gt", ParsedDate::Instant(date)) => Predicate::Range(Bound::Excluded(date), Bound::Unbounded),
+        ("lte", ParsedDate::Instant(date)) => Predicate::Range(Bound::Unbounded, Bound::Included(date)),
+        (_, ParsedDate::Instant(date)) => Predicate::Range(Bound::Unbounded, Bound::Excluded(date)),
+        (_, ParsedDate::Day(day)) => Predicate::Range(Bound::Unbounded, Bound::Excluded(start_of_day(day))),
+    };
+
+    Ok(predicate)
+}
+
+fn is_empty_range(bounds: (Bound<NaiveDateTime>, Bound<NaiveDateTime>)) -> bool {
+    match bounds {
+        (Bound::Included(lower), Bound::Included(upper)) => lower > upper,
+        (Bound::Included(lower) | Bound::Excluded(lower), Bound::Included(upper) | Bound::Excluded(upper)) => {
+            lower >= upper
+        }
+        _ => false,
+    }
+}
+
+fn tighter_lower(a: Bound<NaiveDateTime>, b: Bound<NaiveDateTime>) -> Bound<NaiveDateTime> {
+    match (a, b) {
+        (Bound::Unbounded, other) | (other, Bound::Unbounded) => other,
+        (Bound::Included(x), Bound::Included(y)) => Bound::Included(x.max(y)),
+        (Bound::Excluded(x), Bound::Excluded(y)) => Bound::Excluded(x.max(y)),
+        (Bound::Included(x), Bound::Excluded(y)) | (Bound::Excluded(y), Bound::Included(x)) => {
+            if x > y { Bound::Included(x) } else { Bound::Excluded(y) }
+        }
+    }
+}
+
+fn tighter_upper(a: Bound<NaiveDateTime>, b: Bound<NaiveDateTime>) -> Bound<NaiveDateTime> {
+    match (a, b) {
+        (Bound::Unbounded, other) | (other, Bound::Unbounded) => other,
+        (Bound::Included(x), Bound::Included(y)) => Bound::Included(x.min(y)),
+        (Bound::Excluded(x), Bound::Excluded(y)) => Bound::Excluded(x.min(y)),
+        (Bound::Included(x), Bound::Excluded(y)) | (Bound::Excluded(y), Bound::Included(x)) => {
+            if x < y { Bound::Included(x) } else { Bound::Excluded(y) }
+        }
+    }
+}
+
+impl FieldFilter {
+    pub fn matches(&self, record: &DBSchema) -> bool {
+        self.matches_value(field_ref(record, self.field))
+    }
+
+    fn matches_value(&self, value: FieldRef) -> bool {
+        match &self.predicate {
+            Predicate::Eq(expected) => value_matches(&value, expected),
+            Predicate::In(values) => values.iter().any(|expected| value_matches(&value, expected)),
+            Predicate::Range(lower, upper) => match value {
+                FieldRef::Date(date) => (*lower, *upper).contains(&date),
+                _ => false,
+            },
+            Predicate::Prefix(prefix) => match value {
+                FieldRef::Text(text) => text.starts_with(prefix.as_str()),
+                _ => false,
+            },
+        }
+    }
+}
+
+fn value_matches(value: &FieldRef, expected: &FilterValue) -> bool {
+    match (value, expected) {
+        (FieldRef::Text(text), FilterValue::Text(expected)) => *text == expected,
+        (FieldRef::Status(status), FilterValue::Status(expected)) => *status == expected,
+        (FieldRef::Date(date), FilterValue::Date(expected)) => date == expected,
+        (FieldRef::Date(date), FilterValue::Day(expected)) => date.date() == *expected,
+        _ => false,
+    }
+}
+
+impl RecordFilter {
+    /// Parses `field` / `field.op` parameters into a filter. Keys listed in
+    /// `reserved` (paging, sorting, ...) are skipped; `start_date`/`end_date`
+    /// are kept as aliases for `opened.gte`/`opened.lte` and `range` for
+    /// `opened.range`. Date ranges that end before they start are rejected.
+    pub fn parse(
+        params: &HashMap<String, String>,
+        reserved: &[&str],
+    ) -> Result<RecordFilter, FilterError> {
+        let mut names: Vec<&String> = params.keys().collect();
+        names.sort();
+        let mut filters = vec![];
+
+        for name in names {
+            if reserved.contains(&name.as_str()) {
+                continue;
+            }
+            let value = params[name].trim();
+
+            let (field_name, op) = match name.as_str() {
+                "start_date" => ("opened", "gte"),
+                "end_date" => ("opened", "lte"),
+                "range" => ("opened", "range"),
+                other => other.split_once('.').unwrap_or((other, "eq")),
+            };
+
+            let field = RecordField::from_str(field_name).map_err(|_| FilterError {
+                code: "unknown_field",
+                field: name.to_string(),
+                message: format!("'{field_name}' is not a field of a permit record"),
+            })?;
+
+            let predicate = match op {
+                "eq" => Predicate::Eq(parse_value(name, field, value)?),
+                "in" => Predicate::In(
+                    value
+                        .split(',')
+                        .map(|item| parse_value(name, field, item.trim()))
+                        .collect::<Result<Vec<_>, _>>()?,
+                ),
+                "gte" | "gt" | "lte" | "lt" | "range" if field.is_date() => parse_range(name, op, value)?,
+                "prefix" if field.supports_prefix() => Predicate::Prefix(value.to_string()),
+                _ => {
+                    return Err(FilterError {
+                        code: "unsupported_operator",
+                        field: name.to_string(),
+                        message: format!("'{op}' is not supported on '{field}'"),
+                    });
+                }
+            };
+
+            filters.push(FieldFilter { field, predicate });
+        }
+
+        let filter = RecordFilter { filters };
+        for field in RecordField::ALL.into_iter().filter(RecordField::is_date) {
+            if is_empty_range(filter.date_bounds(field)) {
+                let name = match field {
+                    RecordField::Opened if params.contains_key("start_date") => "start_date".to_string(),
+                    _ => field.to_string(),
+                };
+                return Err(invalid_value(
+                    &name,
+                    format!("The {field} range ends before it starts"),
+                ));
+            }
+        }
+
+        Ok(filter)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub fn matches(&self, record: &DBSchema) -> bool {
+        self.filters.iter().all(|filter| filter.matches(record))
+    }
+
+    /// The values a text field is restricted to by `eq`/`in` filters, or
+    /// `None` when the field is unconstrained.
+    fn allowed_values(&self, field: RecordField) -> Option<HashSet<String>> {
+        let mut allowed: Option<HashSet<String>> = None;
+
+        for filter in self.filters.iter().filter(|filter| filter.field == field) {
+            let values: HashSet<String> = match &filter.predicate {
+                Predicate::Eq(value) => [filter_value_text(value)].into_iter().collect(),
+                Predicate::In(values) => values.iter().map(filter_value_text).collect(),
+                _ => continue,
+            };
+
+            allowed = Some(match allowed {
+                Some(current) => current.intersection(&values).cloned().collect(),
+                None => values,
+            });
+        }
+
+        allowed
+    }
+
+    fn date_bounds(&self, field: RecordField) -> (Bound<NaiveDateTime>, Bound<NaiveDateTime>) {
+        let mut lower = Bound::Unbounded;
+        let mut upper = Bound::Unbounded;
+
+        for filter in self.filters.iter().filter(|filter| filter.field == field) {
+            let (lo, hi) = match &filter.predicate {
+                Predicate::Range(lo, hi) => (*lo, *hi),
+                Predicate::Eq(FilterValue::Date(date)) => (Bound::Included(*date), Bound::Included(*date)),
+                Predicate::Eq(FilterValue::Day(day)) => {
+                    (Bound::Included(start_of_day(*day)), Bound::Excluded(next_day(*day)))
+                }
+                _ => continue,
+            };
+            lower = tighter_lower(lower, lo);
+            upper = tighter_upper(upper, hi);
+        }
+
+        (lower, upper)
+    }
+
+    /// Whether the filter only looks at what `composite_index` entries and
+    /// `main_db` keys already hold: client, county and county_status, plus
+    /// `opened` ranges. Such filters never need a record decoded.
+    pub fn answerable_from_index(&self) -> bool {
+        self.filters.iter().all(|filter| match filter.field {
+            RecordField::Client | RecordField::County | RecordField::CountyStatus => true,
+            RecordField::Opened => matches!(filter.predicate, Predicate::Range(..) | Predicate::Eq(_)),
+            _ => false,
+        })
+    }
+
+    /// Checks the client, county and county_status filters against a
+    /// `composite_index` entry. Filters on other fields are ignored.
+    pub fn matches_index_key(&self, index_key: &KeySchema) -> bool {
+        self.filters.iter().all(|filter| match filter.field {
+            RecordField::Client => filter.matches_value(FieldRef::Text(&index_key.client)),
+            RecordField::County => filter.matches_value(FieldRef::Text(&index_key.county)),
+            RecordField::CountyStatus => filter.matches_value(FieldRef::Status(&index_key.county_status)),
+            _ => true,
+        })
+    }
+
+    /// `main_db` keys start with `{opened:?}`, so an `opened` range maps onto
+    /// a key range. The bounds are exact for `opened` ranges and equality,
+    /// but scans still check every candidate with [`RecordFilter::matches`]
+    /// for the other fields.
+    pub fn key_bounds(&self) -> (Bound<String>, Bound<String>) {
+        let (lower, upper) = self.date_bounds(RecordField::Opened);
+
+        let lower = match lower {
+            Bound::Included(date) => Bound::Included(format!("{date:?}")),
+            Bound::Excluded(date) => Bound::Included(format!("{date:?}.")),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let upper = match upper {
+            Bound::Included(date) => Bound::Excluded(format!("{date:?}.")),
+            Bound::Excluded(date) => Bound::Excluded(format!("{date:?}")),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        (lower, upper)
+    }
+
+    /// The `opened` range as whole `YYYY-MM` months, for answering from the
+    /// `counters` database. `None` when either end falls inside a month.
+    pub fn opened_month_bounds(&self) -> Option<(Bound<String>, Bound<String>)> {
+        let month_start = |date: NaiveDateTime| {
+            (date.day() == 1 && date.time() == NaiveTime::MIN).then(|| date.format("%Y-%m").to_string())
+        };
+        let (lower, upper) = self.date_bounds(RecordField::Opened);
+
+        let lower = match lower {
+            Bound::Included(date) => Bound::Included(month_start(date)?),
+            Bound::Excluded(_) => return None,
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let upper = match upper {
+            Bound::Excluded(date) => Bound::Excluded(month_start(date)?),
+            Bound::Included(_) => return None,
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        Some((lower, upper))
+    }
+}
+
+fn filter_value_text(value: &FilterValue) -> String {
+    match value {
+        FilterValue::Text(text) => text.to_owned(),
+        FilterValue::Status(status) => status.to_string(),
+        FilterValue::Date(date) => format!("{date:?}"),
+        FilterValue::Day(day) => day.to_string(),
+    }
+}
+
+/// Looks up the `main_db` keys of the permit numbers the filter allows in
+/// `permit_index`, or else of every `composite_index` entry it allows.
+/// Returns `None` when none of permit_number, client, county and
+/// county_status is constrained, in which case the indexes can't narrow
+/// anything down.
+fn candidate_keys(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    filter: &RecordFilter,
+) -> heed::Result<Option<BTreeSet<String>>> {
+    if let Some(permit_numbers) = filter.allowed_values(RecordField::PermitNumber) {
+        let mut keys = BTreeSet::new();
+        for permit_number in permit_numbers {
+            if let Some(key) = db_handles.permit_index.get(rtxn, &permit_number)? {
+                keys.insert(key.to_string());
+            }
+        }
+        return Ok(Some(keys));
+    }
+
+    let clients = filter.allowed_values(RecordField::Client);
+    let counties = filter.allowed_values(RecordField::County);
+    let statuses = filter.allowed_values(RecordField::CountyStatus);
+
+    if clients.is_none() && counties.is_none() && statuses.is_none() {
+        return Ok(None);
+    }
+
+    let allows = |allowed: &Option<HashSet<String>>, value: &str| match allowed {
+        Some(allowed) => allowed.contains(value),
+        None => true,
+    };
+
+    let mut index_keys = vec![];
+
+    if let (Some(clients), Some(counties), Some(statuses)) = (&clients, &counties, &statuses) {
+        for client in clients {
+            for county in counties {
+                for status in statuses {
+                    if let Ok(county_status) = Status::from_str(status) {
+                        index_keys.push(KeySchema {
+                            client: client.to_owned(),
+                            county: county.to_owned(),
+                            county_status,
+                        });
+                    }
+                }
+            }
+        }
+    } else {
+        let index = db_handles.composite_index.remap_data_type::<DecodeIgnore>();
+        for entry in index.iter(rtxn)? {
+            let (key, _) = entry?;
+            if allows(&clients, &key.client)
+                && allows(&counties, &key.county)
+                && allows(&statuses, &key.county_status.to_string())
+            {
+                index_keys.push(key);
+            }
+        }
+    }
+
+    let mut keys = BTreeSet::new();
+    for index_key in index_keys {
+        if let Some(set) = db_handles.composite_index.get(rtxn, &index_key)? {
+            keys.extend(set);
+        }
+    }
+
+    Ok(Some(keys))
+}
+
+/// Runs the filter against the database, handing every matching record to
+/// `visit` in `main_db` key order, or the reverse of it when `descending`,
+/// until it returns `ControlFlow::Break`.
+pub fn for_each_match<F>(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    filter: &RecordFilter,
+    descending: bool,
+    visit: F,
+) -> heed::Result<()>
+where
+    F: FnMut(String, DBSchema) -> ControlFlow<()>,
+{
+    for_each_match_after(rtxn, db_handles, filter, descending, None, visit)
+}
+
+/// [`for_each_match`] resuming past the `main_db` key `after`, for picking a
+/// listing up where an earlier page stopped without reading what it covered.
+pub fn for_each_match_after<F>(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    filter: &RecordFilter,
+    descending: bool,
+    after: Option<&str>,
+    mut visit: F,
+) -> heed::Result<()>
+where
+    F: FnMut(String, DBSchema) -> ControlFlow<()>,
+{
+    let (lower, upper) = filter.key_bounds();
+    let mut bounds = (lower.as_ref().map(String::as_str), upper.as_ref().map(String::as_str));
+    if let Some(after) = after {
+        match descending {
+            true => match bounds.1 {
+                Bound::Included(upper) if upper < after => {}
+                Bound::Excluded(upper) if upper <= after => {}
+                _ => bounds.1 = Bound::Excluded(after),
+            },
+            false => match bounds.0 {
+                Bound::Included(lower) if lower > after => {}
+                Bound::Excluded(lower) if lower >= after => {}
+                _ => bounds.0 = Bound::Excluded(after),
+            },
+        }
+    }
+
+    if let Some(keys) = candidate_keys(rtxn, db_handles, filter)? {
+        let keys: Box<dyn Iterator<Item = String>> = match descending {
+            true => Box::new(keys.into_iter().rev()),
+            false => Box::new(keys.into_iter()),
+        };
+        for key in keys {
+            if !RangeBounds::<str>::contains(&bounds, key.as_str()) {
+                continue;
+            }
+            if let Some(record) = db_handles.main_db.get(rtxn, &key)?
+                && filter.matches(&record)
+                && visit(key, record).is_break()
+            {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    let entries = match descending {
+        true => Box::new(db_handles.main_db.rev_range(rtxn, &bounds)?) as Box<dyn Iterator<Item = _>>,
+        false => Box::new(db_handles.main_db.range(rtxn, &bounds)?),
+    };
+    for entry in entries {
+        let (key, record) = entry?;
+        if filter.matches(&record) && visit(key.to_string(), record).is_break() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn collect_matches(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    filter: &RecordFilter,
+) -> heed::Result<Vec<(String, DBSchema)>> {
+    let mut records = vec![];
+    for_each_match(rtxn, db_handles, filter, false, |key, record| {
+        records.push((key, record));
+        ControlFlow::Continue(())
+    })?;
+    Ok(records)
+}
+
+/// [`for_each_match`] over soft-deleted records. Tombstones keep their
+/// `main_db` key, so the same `opened` key range applies.
+pub fn for_each_deleted_match<F>(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    filter: &RecordFilter,
+    descending: bool,
+    mut visit: F,
+) -> heed::Result<()>
+where
+    F: FnMut(String, Tombstone) -> ControlFlow<()>,
+{
+    let (lower, upper) = filter.key_bounds();
+    let bounds = (lower.as_ref().map(String::as_str), upper.as_ref().map(String::as_str));
+
+    let entries = match descending {
+        true => Box::new(db_handles.deleted_db.rev_range(rtxn, &bounds)?) as Box<dyn Iterator<Item = _>>,
+        false => Box::new(db_handles.deleted_db.range(rtxn, &bounds)?),
+    };
+    for entry in entries {
+        let (key, tombstone) = entry?;
+        if filter.matches(&tombstone.record) && visit(key.to_string(), tombstone).is_break() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Soft-deleted records matching the filter, in key order.
+pub fn collect_deleted_matches(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    filter: &RecordFilter,
+) -> heed::Result<Vec<(String, Tombstone)>> {
+    let mut tombstones = vec![];
+    for_each_deleted_match(rtxn, db_handles, filter, false, |key, tombstone| {
+        tombstones.push((key, tombstone));
+        ControlFlow::Continue(())
+    })?;
+    Ok(tombstones)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{datetime, record};
+
+    fn filter(params: &[(&str, &str)]) -> Result<RecordFilter, FilterError> {
+        let params = params.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        RecordFilter::parse(&params, &[])
+    }
+
+    /// `main_db` keys are `"{opened:?}-{uuid}"`, and `{:?}` leaves the
+    /// fraction off whole seconds, so `-` and `.` decide the order right at
+    /// the boundaries.
+    #[test]
+    fn key_bounds_agree_with_matches_on_opened() {
+        let opened = [
+            "2024-01-04T23:59:59.999",
+            "2024-01-05T00:00:00",
+            "2024-01-05T09:59:59.999999999",
+            "2024-01-05T10:00:00",
+            "2024-01-05T10:00:00.001",
+            "2024-01-05T10:00:00.500",
+            "2024-01-05T10:00:00.500100",
+            "2024-01-05T10:00:01",
+            "2024-01-06T00:00:00",
+        ]
+        .map(datetime);
+        let uuids = ["00000000-0000-0000-0000-000000000000", "ffffffff-ffff-ffff-ffff-ffffffffffff"];
+
+        let filters = [
+            vec![("opened.gt", "2024-01-05T10:00:00")],
+            vec![("opened.gte", "2024-01-05T10:00:00")],
+            vec![("opened.lt", "2024-01-05T10:00:00")],
+            vec![("opened.lte", "2024-01-05T10:00:00")],
+            vec![("opened.gt", "2024-01-05T10:00:00.500")],
+            vec![("opened.lte", "2024-01-05T10:00:00.500")],
+            vec![("opened", "2024-01-05T10:00:00")],
+            vec![("opened", "2024-01-05")],
+            vec![("opened.gt", "2024-01-04")],
+            vec![("opened.lt", "2024-01-06")],
+            vec![("start_date", "2024-01-05"), ("end_date", "2024-01-05")],
+            vec![("opened.gte", "2024-01-05T00:00:00"), ("opened.lt", "2024-01-05T10:00:00.500")],
+        ];
+
+        for params in &filters {
+            let filter = filter(params).unwrap();
+            let (lower, upper) = filter.key_bounds();
+            let bounds = (lower.as_ref().map(String::as_str), upper.as_ref().map(String::as_str));
+
+            for opened in opened {
+                for uuid in uuids {
+                    let key = format!("{opened:?}-{uuid}");
+                    assert_eq!(
+                        RangeBounds::<str>::contains(&bounds, key.as_str()),
+                        filter.matches(&record("P1", "acme", opened)),
+                        "{params:?} on {key}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn key_bounds_are_unbounded_without_an_opened_filter() {
+        let filter = filter(&[("county", "king")]).unwrap();
+        assert_eq!(filter.key_bounds(), (Bound::Unbounded, Bound::Unbounded));
+    }
+
+    #[test]
+    fn opened_month_bounds_need_whole_months() {
+        let whole = filter(&[("opened.gte", "2024-01-01"), ("opened.lt", "2024-03-01")]).unwrap();
+        assert_eq!(
+            whole.opened_month_bounds(),
+            Some((Bound::Included("2024-01".to_string()), Bound::Excluded("2024-03".to_string())))
+        );
+
+        let partial = filter(&[("opened.gte", "2024-01-02"), ("opened.lt", "2024-03-01")]).unwrap();
+        assert_eq!(partial.opened_month_bounds(), None);
+    }
+
+    #[test]
+    fn parse_rejects_unknown_fields_and_operators() {
+        let error = filter(&[("colour", "red")]).unwrap_err();
+        assert_eq!((error.code, error.field.as_str()), ("unknown_field", "colour"));
+
+        let error = filter(&[("client.gt", "a")]).unwrap_err();
+        assert_eq!((error.code, error.field.as_str()), ("unsupported_operator", "client.gt"));
+
+        let error = filter(&[("opened.gte", "yesterday-ish")]).unwrap_err();
+        assert_eq!((error.code, error.field.as_str()), ("invalid_value", "opened.gte"));
+    }
+
+    #[test]
+    fn parse_rejects_ranges_that_end_before_they_start() {
+        let error = filter(&[("start_date", "2024-02-01"), ("end_date", "2024-01-01")]).unwrap_err();
+        assert_eq!((error.code, error.field.as_str()), ("invalid_value", "start_date"));
+
+        assert!(filter(&[("opened.gt", "2024-01-05T10:00:00"), ("opened.lt", "2024-01-05T10:00:00")]).is_err());
+        assert!(filter(&[("opened.gte", "2024-01-05T10:00:00"), ("opened.lte", "2024-01-05T10:00:00")]).is_ok());
+    }
+
+    #[test]
+    fn parse_combines_in_and_eq_into_allowed_values() {
+        let filter = filter(&[("county.in", "king, pierce"), ("county", "king")]).unwrap();
+        assert_eq!(
+            filter.allowed_values(RecordField::County),
+            Some(HashSet::from(["king".to_string()]))
+        );
+        assert_eq!(filter.allowed_values(RecordField::Client), None);
+    }
+}
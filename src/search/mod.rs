@@ -0,0 +1,262 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+use heed::{Env, RoTxn, RwTxn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::ApiError,
+    pagination::{decode_token, encode_token},
+    struct_definitions::{DBHandles, DBSchema, FilterError},
+};
+
+/// Splits text into lowercase alphanumeric terms.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// How often each term occurs across the searchable fields of a record.
+fn term_frequencies(record: &DBSchema) -> HashMap<String, u32> {
+    let mut frequencies = HashMap::new();
+    for field in [&record.address, &record.client, &record.permit_number, &record.permit_link] {
+        for term in tokenize(field) {
+            *frequencies.entry(term).or_insert(0) += 1;
+        }
+    }
+    frequencies
+}
+
+/// The `search_postings` key of `term` in the record under `key`. Terms are
+/// alphanumeric, so the NUL can only ever be the separator.
+fn posting_key(term: &str, key: &str) -> String {
+    format!("{term}\0{key}")
+}
+
+/// Adds a record's terms to `search_postings`.
+pub fn index_text(db_handles: &DBHandles, wtxn: &mut RwTxn, key: &str, record: &DBSchema) -> heed::Result<()> {
+    for (term, frequency) in term_frequencies(record) {
+        db_handles.search_postings.put(wtxn, &posting_key(&term, key), &frequency)?;
+    }
+
+    Ok(())
+}
+
+/// Removes a record's terms from `search_postings`.
+pub fn unindex_text(db_handles: &DBHandles, wtxn: &mut RwTxn, key: &str, record: &DBSchema) -> heed::Result<()> {
+    for term in term_frequencies(record).into_keys() {
+        db_handles.search_postings.delete(wtxn, &posting_key(&term, key))?;
+    }
+
+    Ok(())
+}
+
+/// Rebuilds `search_postings` from `main_db`, returning how many records
+/// were indexed.
+pub fn rebuild_search_index(env: &Env, db_handles: &DBHandles) -> heed::Result<usize> {
+    let mut wtxn = env.write_txn()?;
+    db_handles.search_postings.clear(&mut wtxn)?;
+
+    let mut postings = vec![];
+    let mut records = 0;
+    for entry in db_handles.main_db.iter(&wtxn)? {
+        let (key, record) = entry?;
+        for (term, frequency) in term_frequencies(&record) {
+            postings.push((posting_key(&term, key), frequency));
+        }
+        records += 1;
+    }
+
+    postings.sort_unstable();
+    for (posting, frequency) in postings {
+        db_handles.search_postings.put(&mut wtxn, &posting, &frequency)?;
+    }
+    wtxn.commit()?;
+
+    Ok(records)
+}
+
+/// A parsed `q`: alternatives separated by `OR`, each a list of terms that
+/// must all match. `AND` between terms is accepted but implied.
+#[derive(Debug)]
+pub struct TextQuery {
+    alternatives: Vec<Vec<String>>,
+}
+
+impl TextQuery {
+    pub fn parse(q: &str) -> Result<TextQuery, FilterError> {
+        let mut alternatives = vec![vec![]];
+
+        for word in q.split_whitespace() {
+            match word {
+                "OR" => alternatives.push(vec![]),
+                "AND" => {}
+                word => alternatives.last_mut().unwrap().extend(tokenize(word)),
+            }
+        }
+        alternatives.retain(|terms| !terms.is_empty());
+
+        if alternatives.is_empty() {
+            return Err(FilterError {
+                code: "invalid_value",
+                field: "q".to_string(),
+                message: "q needs at least one letter or digit to search for".to_string(),
+            });
+        }
+
+        Ok(TextQuery { alternatives })
+    }
+
+    /// Keys of the matching records with their scores, best first. Every
+    /// query term is a prefix, and a record scores the summed frequency of
+    /// all the indexed terms it matched.
+    pub fn run(&self, rtxn: &RoTxn, db_handles: &DBHandles) -> heed::Result<Vec<(String, u32)>> {
+        let mut postings: HashMap<&str, HashMap<String, u32>> = HashMap::new();
+        for term in self.alternatives.iter().flatten() {
+            if postings.contains_key(term.as_str()) {
+                continue;
+            }
+            let mut matches: HashMap<String, u32> = HashMap::new();
+            for entry in db_handles.search_postings.prefix_iter(rtxn, term)? {
+                let (posting, frequency) = entry?;
+                if let Some((_, key)) = posting.split_once('\0') {
+                    *matches.entry(key.to_owned()).or_insert(0) += frequency;
+                }
+            }
+            postings.insert(term, matches);
+        }
+
+        let mut matched: HashSet<&str> = HashSet::new();
+        for terms in &self.alternatives {
+            let (first, rest) = terms.split_first().expect("alternatives are never empty");
+            matched.extend(
+                postings[first.as_str()]
+                    .keys()
+                    .filter(|key| rest.iter().all(|term| postings[term.as_str()].contains_key(*key)))
+                    .map(String::as_str),
+            );
+        }
+
+        let mut scored: Vec<(String, u32)> = matched
+            .into_iter()
+            .map(|key| {
+                let score = postings.values().filter_map(|matches| matches.get(key)).sum();
+                (key.to_owned(), score)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        Ok(scored)
+    }
+}
+
+/// Where a page of search results stopped, tied to the query it came from.
+#[derive(Serialize, Deserialize)]
+struct SearchCursor {
+    q: String,
+    score: u32,
+    key: String,
+}
+
+/// A page of `(key, score)` search results.
+pub struct ResultsPage {
+    pub results: Vec<(String, u32)>,
+    pub next_cursor: Option<String>,
+}
+
+/// Up to `limit` of the results [`TextQuery::run`] found for `q`, starting
+/// after `cursor`, plus the cursor of the next page when there is one.
+pub fn page_of_results(
+    scored: Vec<(String, u32)>,
+    q: &str,
+    cursor: Option<&str>,
+    limit: usize,
+) -> Result<ResultsPage, ApiError> {
+    let start = match cursor.map(decode_token::<SearchCursor>).transpose()? {
+        Some(cursor) if cursor.q != q => {
+            return Err(ApiError::validation("cursor", "The cursor was issued for a different query"));
+        }
+        Some(cursor) => scored.partition_point(|(key, score)| {
+            (Reverse(*score), key.as_str()) <= (Reverse(cursor.score), cursor.key.as_str())
+        }),
+        None => 0,
+    };
+
+    let mut results: Vec<(String, u32)> = scored.into_iter().skip(start).take(limit.saturating_add(1)).collect();
+    let next_cursor = match results.len() > limit {
+        true => {
+            results.truncate(limit);
+            results.last().map(|(key, score)| {
+                encode_token(&SearchCursor {
+                    q: q.to_string(),
+                    score: *score,
+                    key: key.clone(),
+                })
+            })
+        }
+        false => None,
+    };
+
+    Ok(ResultsPage { results, next_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempEnv, datetime, record};
+
+    #[test]
+    fn tokenize_splits_on_anything_but_letters_and_digits() {
+        let terms: Vec<String> = tokenize("123 Main-St., Apt #4 (ACME) http://x/P-17").collect();
+        assert_eq!(terms, ["123", "main", "st", "apt", "4", "acme", "http", "x", "p", "17"]);
+    }
+
+    #[test]
+    fn tokenize_keeps_non_ascii_letters() {
+        let terms: Vec<String> = tokenize("Café Über-Straße").collect();
+        assert_eq!(terms, ["café", "über", "straße"]);
+        assert_eq!(tokenize("  --  ").count(), 0);
+    }
+
+    #[test]
+    fn postings_follow_records_in_and_out_of_the_index() {
+        let temp = TempEnv::open();
+        let db_handles = temp.set_up();
+        let mut main = record("P1", "acme", datetime("2024-01-01T00:00:00"));
+        main.address = "1 Main St Main".to_string();
+        let mut maple = record("P2", "acme", datetime("2024-01-02T00:00:00"));
+        maple.address = "9 Maple Ave".to_string();
+        let keyed = temp.insert(&db_handles, &[main, maple]);
+
+        let rtxn = temp.env.read_txn().unwrap();
+        let run = |q: &str| TextQuery::parse(q).unwrap().run(&rtxn, &db_handles).unwrap();
+        assert_eq!(run("ma"), [(keyed[0].0.clone(), 2), (keyed[1].0.clone(), 1)]);
+        assert_eq!(run("maple OR main").len(), 2);
+        assert_eq!(run("maple main"), []);
+        drop(rtxn);
+
+        let mut wtxn = temp.env.write_txn().unwrap();
+        unindex_text(&db_handles, &mut wtxn, &keyed[0].0, &keyed[0].1).unwrap();
+        wtxn.commit().unwrap();
+        let rtxn = temp.env.read_txn().unwrap();
+        let scored = TextQuery::parse("ma").unwrap().run(&rtxn, &db_handles).unwrap();
+        assert_eq!(scored, [(keyed[1].0.clone(), 1)]);
+    }
+
+    #[test]
+    fn result_pages_follow_the_cursor() {
+        let scored: Vec<(String, u32)> = vec![("a".into(), 3), ("b".into(), 2), ("c".into(), 2), ("d".into(), 1)];
+
+        let first = page_of_results(scored.clone(), "x", None, 2).unwrap();
+        assert_eq!(first.results, [("a".to_string(), 3), ("b".to_string(), 2)]);
+        let cursor = first.next_cursor.unwrap();
+        let second = page_of_results(scored.clone(), "x", Some(&cursor), 2).unwrap();
+        assert_eq!(second.results, [("c".to_string(), 2), ("d".to_string(), 1)]);
+        assert!(second.next_cursor.is_none());
+
+        assert!(page_of_results(scored, "y", Some(&cursor), 2).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+
+use heed::{Env, RoTxn};
+use serde_json::json;
+
+use crate::{
+    api_error::ApiError,
+    audit::{AuditContext, Change, WORKFLOW_STAGES_DB, record_change},
+    struct_definitions::{DBHandles, ProcessStatus},
+};
+
+/// The stages `county` accepts: its `workflow_stages` entry, or the built-in
+/// three when it has none.
+pub fn county_stages(rtxn: &RoTxn, db_handles: &DBHandles, county: &str) -> heed::Result<Vec<ProcessStatus>> {
+    Ok(db_handles
+        .workflow_stages
+        .get(rtxn, county)?
+        .unwrap_or_else(|| ProcessStatus::BUILT_IN.to_vec()))
+}
+
+/// County of the live record holding `permit_number`, if there is one.
+pub fn permit_county(rtxn: &RoTxn, db_handles: &DBHandles, permit_number: &str) -> heed::Result<Option<String>> {
+    let Some(key) = db_handles.permit_index.get(rtxn, permit_number)? else {
+        return Ok(None);
+    };
+
+    Ok(db_handles.main_db.get(rtxn, key)?.map(|record| record.county))
+}
+
+/// Rejects a stage the county's pipeline doesn't have. Permits without a
+/// live record are held to the built-in stages.
+pub fn validate_stage(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    county: Option<&str>,
+    stage: &ProcessStatus,
+) -> Result<(), ApiError> {
+    let stages = match county {
+        Some(county) => county_stages(rtxn, db_handles, county)?,
+        None => ProcessStatus::BUILT_IN.to_vec(),
+    };
+    if stages.contains(stage) {
+        return Ok(());
+    }
+
+    let expected: Vec<String> = stages.iter().map(ToString::to_string).collect();
+    let message = match county {
+        Some(county) => format!(
+            "'{stage}' is not a workflow stage of county {county}, expected {}",
+            expected.join(", ")
+        ),
+        None => format!("'{stage}' is not a built-in workflow stage, expected {}", expected.join(", ")),
+    };
+    Err(ApiError::validation("processing_status", message))
+}
+
+/// Whether any county, or the built-in pipeline, has the stage.
+pub fn is_known_stage(rtxn: &RoTxn, db_handles: &DBHandles, stage: &ProcessStatus) -> heed::Result<bool> {
+    if ProcessStatus::BUILT_IN.contains(stage) {
+        return Ok(true);
+    }
+    for entry in db_handles.workflow_stages.iter(rtxn)? {
+        let (_, stages) = entry?;
+        if stages.contains(stage) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Parses a county's stage names, in pipeline order. Built-in names match
+/// regardless of case.
+pub fn parse_stages(names: &[String]) -> Result<Vec<ProcessStatus>, String> {
+    if names.is_empty() {
+        return Err("A county needs at least one workflow stage".to_string());
+    }
+
+    let mut stages: Vec<ProcessStatus> = vec![];
+    for name in names {
+        let stage: ProcessStatus = name.parse().map_err(|_| "Workflow stage names must not be empty".to_string())?;
+        if stages.contains(&stage) {
+            return Err(format!("{stage} is listed more than once"));
+        }
+        stages.push(stage);
+    }
+
+    Ok(stages)
+}
+
+/// Seeds `workflow_stages` from a config file written as
+/// `{"<county>": ["<stage>", ...]}`, returning how many counties it set.
+/// Counties that already have stages keep them, so edits made through
+/// `/workflow-stages` survive a restart. A county whose stages were deleted
+/// is seeded again.
+pub fn load_stage_config(env: &Env, db_handles: &DBHandles, path: &str, text: &str) -> Result<usize, String> {
+    let config: BTreeMap<String, Vec<String>> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+
+    let mut counties = vec![];
+    for (county, names) in config {
+        let stages = parse_stages(&names).map_err(|e| format!("{county}: {e}"))?;
+        counties.push((county, stages));
+    }
+    let audit = AuditContext {
+        actor: "system".to_string(),
+        endpoint: format!("WORKFLOW_STAGES {path}"),
+    };
+
+    seed_stages(env, db_handles, &audit, &counties).map_err(|e| e.to_string())
+}
+
+fn seed_stages(
+    env: &Env,
+    db_handles: &DBHandles,
+    audit: &AuditContext,
+    counties: &[(String, Vec<ProcessStatus>)],
+) -> heed::Result<usize> {
+    let mut wtxn = env.write_txn()?;
+    let mut seeded = 0;
+    for (county, stages) in counties {
+        if db_handles.workflow_stages.get(&wtxn, county)?.is_some() {
+            continue;
+        }
+        db_handles.workflow_stages.put(&mut wtxn, county, stages)?;
+        let after = json!({ "stages": stages });
+        record_change(db_handles, &mut wtxn, audit, Change::created(WORKFLOW_STAGES_DB, county, &after))?;
+        seeded += 1;
+    }
+    wtxn.commit()?;
+
+    Ok(seeded)
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::{Bound, ControlFlow, RangeBounds},
+    str::FromStr,
+};
+
+use heed::RoTxn;
+use serde_json::{Map, Value, json};
+
+use crate::{
+    query_engine::for_each_match,
+    struct_definitions::{DBHandles, DBSchema, FilterError, KeySchema, RecordFilter},
+};
+
+/// Dimensions `/stats/permits` can group by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    County,
+    Client,
+    CountyStatus,
+    ManualStatus,
+    OpenedMonth,
+    OpenedYear,
+}
+
+impl FromStr for GroupBy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "county" => Ok(GroupBy::County),
+            "client" => Ok(GroupBy::Client),
+            "county_status" => Ok(GroupBy::CountyStatus),
+            "manual_status" => Ok(GroupBy::ManualStatus),
+            "opened_month" => Ok(GroupBy::OpenedMonth),
+            "opened_year" => Ok(GroupBy::OpenedYear),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GroupBy::County => "county",
+            GroupBy::Client => "client",
+            GroupBy::CountyStatus => "county_status",
+            GroupBy::ManualStatus => "manual_status",
+            GroupBy::OpenedMonth => "opened_month",
+            GroupBy::OpenedYear => "opened_year",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl GroupBy {
+    /// Parses a comma separated `group_by`. Leaving it out counts everything
+    /// as one group.
+    pub fn parse_list(value: Option<&str>) -> Result<Vec<GroupBy>, FilterError> {
+        let Some(value) = value.filter(|value| !value.trim().is_empty()) else {
+            return Ok(vec![]);
+        };
+
+        let mut group_by: Vec<GroupBy> = vec![];
+        for name in value.split(',').map(str::trim) {
+            let dimension = GroupBy::from_str(name).map_err(|_| FilterError {
+                code: "unknown_field",
+                field: "group_by".to_string(),
+                message: format!(
+                    "Cannot group by '{name}', expected county, client, county_status, manual_status, opened_month or opened_year"
+                ),
+            })?;
+            if !group_by.contains(&dimension) {
+                group_by.push(dimension);
+            }
+        }
+
+        Ok(group_by)
+    }
+
+    fn needs_record(self) -> bool {
+        self == GroupBy::ManualStatus
+    }
+
+    /// The group value from the index entry and `main_db` key alone. Keys
+    /// start with the `{opened:?}` timestamp, so the month and year are its
+    /// first 7 and 4 characters.
+    fn index_value(self, index_key: &KeySchema, key: &str) -> String {
+        match self {
+            GroupBy::County => index_key.county.clone(),
+            GroupBy::Client => index_key.client.clone(),
+            GroupBy::CountyStatus => index_key.county_status.to_string(),
+            GroupBy::OpenedMonth => key.get(..7).unwrap_or(key).to_string(),
+            GroupBy::OpenedYear => key.get(..4).unwrap_or(key).to_string(),
+            GroupBy::ManualStatus => unreachable!("manual_status is not in composite_index"),
+        }
+    }
+
+    fn record_value(self, record: &DBSchema) -> String {
+        match self {
+            GroupBy::County => record.county.clone(),
+            GroupBy::Client => record.client.clone(),
+            GroupBy::CountyStatus => record.county_status.to_string(),
+            GroupBy::ManualStatus => record.manual_status.to_string(),
+            GroupBy::OpenedMonth => record.opened.format("%Y-%m").to_string(),
+            GroupBy::OpenedYear => record.opened.format("%Y").to_string(),
+        }
+    }
+}
+
+/// Record counts per group, plus which database answered.
+pub struct PermitStats {
+    pub counts: BTreeMap<Vec<String>, u64>,
+    pub source: &'static str,
+}
+
+impl PermitStats {
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// One object per group, holding its group values and its count.
+    pub fn rows(&self, group_by: &[GroupBy]) -> Vec<Value> {
+        self.counts
+            .iter()
+            .map(|(values, count)| {
+                let mut row: Map<String, Value> = group_by
+                    .iter()
+                    .zip(values)
+                    .map(|(dimension, value)| (dimension.to_string(), json!(value)))
+                    .collect();
+                row.insert("count".to_string(), json!(count));
+                Value::Object(row)
+            })
+            .collect()
+    }
+}
+
+/// Counts the records matching `filter` per `group_by` group. When both
+/// only touch client, county, county_status and whole months of the opened
+/// date, the counts are summed from `counters`. Other opened ranges fall
+/// back to the `composite_index` entries and the keys they hold, and
+/// anything else decodes every matching record.
+pub fn count_permits(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    filter: &RecordFilter,
+    group_by: &[GroupBy],
+) -> heed::Result<PermitStats> {
+    let mut counts: BTreeMap<Vec<String>, u64> = BTreeMap::new();
+
+    let from_index = filter.answerable_from_index() && !group_by.iter().any(|dimension| dimension.needs_record());
+
+    if from_index && let Some(months) = filter.opened_month_bounds() {
+        let months = (months.0.as_ref().map(String::as_str), months.1.as_ref().map(String::as_str));
+
+        for entry in db_handles.counters.iter(rtxn)? {
+            let (counter_key, count) = entry?;
+            if !filter.matches_index_key(&counter_key.index_key)
+                || !RangeBounds::<str>::contains(&months, counter_key.month.as_str())
+            {
+                continue;
+            }
+            let group = group_by
+                .iter()
+                .map(|dimension| dimension.index_value(&counter_key.index_key, &counter_key.month))
+                .collect();
+            *counts.entry(group).or_default() += count;
+        }
+
+        return Ok(PermitStats {
+            counts,
+            source: "counters",
+        });
+    }
+
+    if from_index {
+        let (lower, upper) = filter.key_bounds();
+        let bounds = (lower.as_ref().map(String::as_str), upper.as_ref().map(String::as_str));
+        let whole_range = matches!(bounds, (Bound::Unbounded, Bound::Unbounded));
+        let by_key = group_by
+            .iter()
+            .any(|dimension| matches!(dimension, GroupBy::OpenedMonth | GroupBy::OpenedYear));
+
+        for entry in db_handles.composite_index.iter(rtxn)? {
+            let (index_key, keys) = entry?;
+            if !filter.matches_index_key(&index_key) {
+                continue;
+            }
+
+            if whole_range && !by_key {
+                let group = group_by.iter().map(|dimension| dimension.index_value(&index_key, "")).collect();
+                *counts.entry(group).or_default() += keys.len() as u64;
+                continue;
+            }
+            for key in keys.iter().filter(|key| RangeBounds::<str>::contains(&bounds, key.as_str())) {
+                let group = group_by.iter().map(|dimension| dimension.index_value(&index_key, key)).collect();
+                *counts.entry(group).or_default() += 1;
+            }
+        }
+
+        return Ok(PermitStats {
+            counts,
+            source: "composite_index",
+        });
+    }
+
+    for_each_match(rtxn, db_handles, filter, false, |_, record| {
+        let group = group_by.iter().map(|dimension| dimension.record_value(&record)).collect();
+        *counts.entry(group).or_default() += 1;
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(PermitStats {
+        counts,
+        source: "main_db",
+    })
+}
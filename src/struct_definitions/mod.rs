@@ -1,8 +1,10 @@
 use core::fmt;
-use std::{collections::HashSet, str::FromStr, sync::Arc};
-use chrono::NaiveDateTime;
+use std::{collections::{HashMap, HashSet}, ops::Bound, str::FromStr, sync::Arc};
+use chrono::{NaiveDate, NaiveDateTime};
 use heed::{types::*, Database, Env};
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::lifecycle::TransitionTable;
 
 pub struct DbEnv {
    pub env: Arc<Env>,
@@ -12,7 +14,231 @@ pub struct DBdata {
    pub db_data: Arc<DBHandles>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, PartialOrd, Eq, Ord)]
+pub struct AppConfig {
+   pub delete_policy: DeletePolicy,
+   pub tombstone_retention_days: u64,
+   pub bulk_payload_limit: usize,
+   pub allow_get_body: bool,
+   pub transitions: TransitionTable,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletePolicy {
+    Cascade,
+    Restrict,
+    Soft
+}
+
+impl fmt::Display for DeletePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            DeletePolicy::Cascade => "cascade",
+            DeletePolicy::Restrict => "restrict",
+            DeletePolicy::Soft => "soft"
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for DeletePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cascade" => Ok(DeletePolicy::Cascade),
+            "restrict" => Ok(DeletePolicy::Restrict),
+            "soft" => Ok(DeletePolicy::Soft),
+            _ => Err(())
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct DeleteOptions {
+    pub policy: Option<DeletePolicy>
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct PurgeOptions {
+    pub older_than_days: Option<u64>
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkMode {
+    #[default]
+    Atomic,
+    BestEffort
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct BulkOptions {
+    pub mode: Option<BulkMode>
+}
+
+/// One item of a `/bulk/records` request: a record plus any processing
+/// states and payments to create under its permit number.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct BulkRecord {
+    #[serde(flatten)]
+    pub record: DBSchema,
+    #[serde(default)]
+    pub processing_states: Vec<ProcessingStatusSchema>,
+    #[serde(default)]
+    pub payments: Vec<Payments>
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ReadOptions {
+    pub include_deleted: Option<bool>
+}
+
+/// Parameters of `/read-record`: paging and sorting, plus any `field` /
+/// `field.op` filters understood by the query engine.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+pub struct RecordListQuery {
+    pub cursor: Option<String>,
+    #[serde(default, deserialize_with = "from_text")]
+    pub page: Option<usize>,
+    #[serde(default, deserialize_with = "from_text")]
+    pub records_per_page: Option<usize>,
+    pub sort: Option<String>,
+    pub sort_key: Option<String>,
+    #[serde(default, deserialize_with = "from_text")]
+    pub include_deleted: Option<bool>,
+    #[serde(flatten)]
+    pub filters: HashMap<String, String>
+}
+
+/// Parameters of `/read-records-by-opened-date`. Either both dates or a
+/// relative `range` must be given.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+pub struct DateRangeQuery {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub range: Option<String>,
+    pub cursor: Option<String>,
+    #[serde(default, deserialize_with = "from_text")]
+    pub page: Option<usize>,
+    #[serde(default, deserialize_with = "from_text")]
+    pub records_per_page: Option<usize>,
+    pub sort: Option<String>,
+    #[serde(default, deserialize_with = "from_text")]
+    pub include_deleted: Option<bool>
+}
+
+impl DateRangeQuery {
+    /// The dates as query engine parameters.
+    pub fn filter_params(&self) -> HashMap<String, String> {
+        [("start_date", &self.start_date), ("end_date", &self.end_date), ("range", &self.range)]
+            .into_iter()
+            .filter_map(|(name, value)| value.as_ref().map(|value| (name.to_string(), value.clone())))
+            .collect()
+    }
+}
+
+/// Parameters of `/stats/permits`: the dimensions to group by, plus the same
+/// filters as the listing endpoints.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+pub struct StatsQuery {
+    pub group_by: Option<String>,
+    #[serde(flatten)]
+    pub filters: HashMap<String, String>
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SimilarQuery {
+    pub min_score: Option<f64>,
+    pub limit: Option<usize>
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TextSearchQuery {
+    pub q: String,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>
+}
+
+/// Parameters of `/read-permits-with-filter`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+pub struct PermitFilterQuery {
+    pub cursor: Option<String>,
+    #[serde(default, deserialize_with = "from_text")]
+    pub page: Option<usize>,
+    #[serde(default, deserialize_with = "from_text")]
+    pub records_per_page: Option<usize>,
+    pub sort: Option<String>,
+    #[serde(default, deserialize_with = "from_text")]
+    pub include_deleted: Option<bool>,
+    #[serde(flatten)]
+    pub filters: HashMap<String, String>
+}
+
+/// Body of `POST /search`. A list under a filter name matches any of its
+/// values, so `{"county": ["a", "b"]}` is the same as `county.in=a,b`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+pub struct SearchRequest {
+    #[serde(default)]
+    pub filters: HashMap<String, SearchValue>,
+    pub cursor: Option<String>,
+    pub sort: Option<String>,
+    pub sort_key: Option<String>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub include_deleted: bool
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SearchValue {
+    One(String),
+    Any(Vec<String>)
+}
+
+impl SearchRequest {
+    /// The filters as query engine parameters.
+    pub fn filter_params(&self) -> HashMap<String, String> {
+        self.filters
+            .iter()
+            .map(|(name, value)| match value {
+                SearchValue::One(value) => (name.clone(), value.clone()),
+                SearchValue::Any(values) if name.contains('.') => (name.clone(), values.join(",")),
+                SearchValue::Any(values) => (format!("{name}.in"), values.join(",")),
+            })
+            .collect()
+    }
+}
+
+/// Query strings carry every value as text, and a flattened struct hands
+/// them over as strings even from JSON, so typed fields parse either form.
+/// An empty value counts as absent.
+fn from_text<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+    T::Err: fmt::Display,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw<T> {
+        Text(String),
+        Value(T),
+    }
+
+    match Option::<Raw<T>>::deserialize(deserializer)? {
+        Some(Raw::Text(text)) if text.trim().is_empty() => Ok(None),
+        Some(Raw::Text(text)) => text
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| de::Error::custom(format!("'{text}' is not a valid value: {e}"))),
+        Some(Raw::Value(value)) => Ok(Some(value)),
+        None => Ok(None),
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub enum Status {
     Active,
     Inactive,
@@ -60,7 +286,12 @@ pub struct DBSchema {
     pub county: String,
     pub county_status: Status,
     pub manual_status: Status,
-    pub address: String
+    pub address: String,
+    /// `address` in USPS standard form, kept up to date by every write.
+    #[serde(default)]
+    pub normalized_address: String,
+    #[serde(default)]
+    pub revision: u64
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
@@ -73,14 +304,56 @@ pub struct UpdateDBSchema {
     pub county: Option<String>,
     pub county_status: Option<Status>,
     pub manual_status: Option<Status>,
-    pub address: Option<String> 
+    pub address: Option<String>,
+    /// Why `county_status` or `manual_status` changed, kept in `status_history`.
+    pub status_reason: Option<String>
+}
+
+/// The two `DBSchema` fields that follow the status transition table.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusField {
+    CountyStatus,
+    ManualStatus
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, PartialOrd, Eq, Ord)]
+impl fmt::Display for StatusField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            StatusField::CountyStatus => "county_status",
+            StatusField::ManualStatus => "manual_status"
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct StatusTransition {
+    pub field: StatusField,
+    pub from: Status,
+    pub to: Status,
+    pub at: NaiveDateTime,
+    pub actor: String,
+    pub reason: Option<String>
+}
+
+/// A processing workflow stage. Counties that configure their own pipeline
+/// in `workflow_stages` use `Custom` stages; the built-in variants keep their
+/// bincode variant indexes, so `Custom` has to stay last.
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub enum ProcessStatus {
     ApprovedWithConditions,
     PendingAdditionalReview,
-    RevisionsReceived
+    RevisionsReceived,
+    Custom(String)
+}
+
+impl ProcessStatus {
+    pub const BUILT_IN: [ProcessStatus; 3] = [
+        ProcessStatus::ApprovedWithConditions,
+        ProcessStatus::PendingAdditionalReview,
+        ProcessStatus::RevisionsReceived
+    ];
 }
 
 impl fmt::Display for ProcessStatus {
@@ -88,12 +361,61 @@ impl fmt::Display for ProcessStatus {
         let s = match self {
             ProcessStatus::ApprovedWithConditions => "ApprovedWithConditions",
             ProcessStatus::PendingAdditionalReview => "PendingAdditionalReview",
-            ProcessStatus::RevisionsReceived => "RevisionsReceived"
+            ProcessStatus::RevisionsReceived => "RevisionsReceived",
+            ProcessStatus::Custom(stage) => stage
         };
         write!(f, "{}", s)
     }
 }
 
+/// How `ProcessStatus` is laid out in bincode rows, matching what the derive
+/// wrote before `Custom` existed.
+#[derive(Deserialize, Serialize)]
+enum StoredProcessStatus {
+    ApprovedWithConditions,
+    PendingAdditionalReview,
+    RevisionsReceived,
+    Custom(String)
+}
+
+/// JSON carries a stage as its name, built-in or not. Bincode keeps the
+/// variant layout of [`StoredProcessStatus`].
+impl Serialize for ProcessStatus {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.collect_str(self);
+        }
+
+        let stored = match self {
+            ProcessStatus::ApprovedWithConditions => StoredProcessStatus::ApprovedWithConditions,
+            ProcessStatus::PendingAdditionalReview => StoredProcessStatus::PendingAdditionalReview,
+            ProcessStatus::RevisionsReceived => StoredProcessStatus::RevisionsReceived,
+            ProcessStatus::Custom(stage) => StoredProcessStatus::Custom(stage.clone())
+        };
+        stored.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProcessStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let name = String::deserialize(deserializer)?;
+            return name
+                .parse()
+                .map_err(|_| de::Error::custom("a processing status must not be empty"));
+        }
+
+        Ok(match StoredProcessStatus::deserialize(deserializer)? {
+            StoredProcessStatus::ApprovedWithConditions => ProcessStatus::ApprovedWithConditions,
+            StoredProcessStatus::PendingAdditionalReview => ProcessStatus::PendingAdditionalReview,
+            StoredProcessStatus::RevisionsReceived => ProcessStatus::RevisionsReceived,
+            StoredProcessStatus::Custom(stage) => ProcessStatus::Custom(stage)
+        })
+    }
+}
+
+/// Built-in stages match regardless of case; any other name is a `Custom`
+/// stage, which only counties that define it accept.
 impl FromStr for ProcessStatus {
     type Err = ();
 
@@ -102,7 +424,8 @@ impl FromStr for ProcessStatus {
             "approvedwithconditions" => Ok(ProcessStatus::ApprovedWithConditions),
             "pendingadditionalreview" => Ok(ProcessStatus::PendingAdditionalReview),
             "revisionsreceived" => Ok(ProcessStatus::RevisionsReceived),
-            _ => Err(())
+            _ if s.trim().is_empty() => Err(()),
+            _ => Ok(ProcessStatus::Custom(s.trim().to_string()))
         }
     }
 }
@@ -112,7 +435,22 @@ pub struct ProcessingStatusSchema {
     pub processing_status: ProcessStatus,
     pub due_date: NaiveDateTime,
     pub assigned_to: String,
-    pub last_modified: NaiveDateTime
+    pub last_modified: NaiveDateTime,
+    #[serde(default)]
+    pub revision: u64
+}
+
+/// Which processing state of a permit is current, with its status copied
+/// so status filters don't have to read the state itself.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct CurrentProcessing {
+    pub key: String,
+    pub processing_status: ProcessStatus
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ProcessingReadOptions {
+    pub current: Option<bool>
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
@@ -123,12 +461,24 @@ pub struct UpdateProcessingStatusSchema {
     pub last_modified: Option<NaiveDateTime>
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct DueQuery {
+    pub within: Option<String>
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct QueueQuery {
+    pub sort: Option<String>
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Payments {
     pub payment: String,
     pub date: NaiveDateTime,
     pub amount: u64,
-    pub status: String
+    pub status: String,
+    #[serde(default)]
+    pub revision: u64
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
@@ -140,16 +490,287 @@ pub struct UpdatePayment {
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Tombstone {
+    pub record: DBSchema,
+    pub deleted_at: NaiveDateTime
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    SoftDelete,
+    Restore,
+    Purge
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Option<String>,
+    pub after: Option<String>
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub key: String,
+    pub database: String,
+    pub action: AuditAction,
+    pub endpoint: String,
+    pub actor: String,
+    pub timestamp: NaiveDateTime,
+    pub changes: Vec<FieldChange>
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AuditQuery {
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+    /// The `next` of an earlier page, to continue after.
+    pub after: Option<String>
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct KeySchema {
     pub client: String,
     pub county: String,
     pub county_status: Status
 }
 
+impl From<&DBSchema> for KeySchema {
+    fn from(record: &DBSchema) -> Self {
+        KeySchema {
+            client: record.client.to_owned(),
+            county: record.county.to_owned(),
+            county_status: record.county_status.clone()
+        }
+    }
+}
+
+/// Key of the `counters` database: a `composite_index` entry narrowed to
+/// the records opened in one `YYYY-MM` month.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CounterKey {
+    pub index_key: KeySchema,
+    pub month: String
+}
+
 #[derive(Clone)]
 pub struct DBHandles {
     pub main_db: Database<Str, SerdeBincode<DBSchema>>,
     pub composite_index: Database<SerdeBincode<KeySchema>, SerdeBincode<HashSet<String>>>,
     pub processing_state: Database<Str, SerdeBincode<ProcessingStatusSchema>>,
-    pub payments_db: Database<Str, SerdeBincode<Payments>>
-}
\ No newline at end of file
+    pub payments_db: Database<Str, SerdeBincode<Payments>>,
+    pub permit_index: Database<Str, Str>,
+    pub deleted_db: Database<Str, SerdeBincode<Tombstone>>,
+    pub deleted_permit_index: Database<Str, Str>,
+    pub audit_log: Database<Str, SerdeBincode<AuditEntry>>,
+    pub audit_key_index: Database<Str, Unit>,
+    pub search_postings: Database<Str, SerdeBincode<u32>>,
+    pub counters: Database<SerdeBincode<CounterKey>, SerdeBincode<u64>>,
+    pub status_history: Database<Str, SerdeBincode<Vec<StatusTransition>>>,
+    pub due_index: Database<Str, Str>,
+    pub assignee_index: Database<Str, SerdeBincode<HashSet<String>>>,
+    pub current_processing: Database<Str, SerdeBincode<CurrentProcessing>>,
+    pub workflow_stages: Database<Str, SerdeBincode<Vec<ProcessStatus>>>
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordField {
+    PermitLink,
+    PermitNumber,
+    Client,
+    Opened,
+    LastUpdated,
+    StatusUpdated,
+    County,
+    CountyStatus,
+    ManualStatus,
+    Address
+}
+
+impl RecordField {
+    pub const ALL: [RecordField; 10] = [
+        RecordField::PermitLink,
+        RecordField::PermitNumber,
+        RecordField::Client,
+        RecordField::Opened,
+        RecordField::LastUpdated,
+        RecordField::StatusUpdated,
+        RecordField::County,
+        RecordField::CountyStatus,
+        RecordField::ManualStatus,
+        RecordField::Address
+    ];
+
+    pub fn is_date(&self) -> bool {
+        matches!(self, RecordField::Opened | RecordField::LastUpdated | RecordField::StatusUpdated)
+    }
+
+    pub fn is_status(&self) -> bool {
+        matches!(self, RecordField::CountyStatus | RecordField::ManualStatus)
+    }
+
+    pub fn supports_prefix(&self) -> bool {
+        matches!(self, RecordField::Address | RecordField::PermitNumber)
+    }
+}
+
+impl fmt::Display for RecordField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            RecordField::PermitLink => "permit_link",
+            RecordField::PermitNumber => "permit_number",
+            RecordField::Client => "client",
+            RecordField::Opened => "opened",
+            RecordField::LastUpdated => "last_updated",
+            RecordField::StatusUpdated => "status_updated",
+            RecordField::County => "county",
+            RecordField::CountyStatus => "county_status",
+            RecordField::ManualStatus => "manual_status",
+            RecordField::Address => "address"
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for RecordField {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "permit_link" => Ok(RecordField::PermitLink),
+            "permit_number" => Ok(RecordField::PermitNumber),
+            "client" => Ok(RecordField::Client),
+            "opened" => Ok(RecordField::Opened),
+            "last_updated" => Ok(RecordField::LastUpdated),
+            "status_updated" => Ok(RecordField::StatusUpdated),
+            "county" => Ok(RecordField::County),
+            "county_status" => Ok(RecordField::CountyStatus),
+            "manual_status" => Ok(RecordField::ManualStatus),
+            "address" => Ok(RecordField::Address),
+            _ => Err(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Text(String),
+    Status(Status),
+    Date(NaiveDateTime),
+    Day(NaiveDate)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Eq(FilterValue),
+    In(Vec<FilterValue>),
+    Range(Bound<NaiveDateTime>, Bound<NaiveDateTime>),
+    Prefix(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldFilter {
+    pub field: RecordField,
+    pub predicate: Predicate
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RecordFilter {
+    pub filters: Vec<FieldFilter>
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FilterError {
+    pub code: &'static str,
+    pub field: String,
+    pub message: String
+}
+
+#[cfg(test)]
+mod tests {
+    use heed::{BytesDecode, BytesEncode, types::SerdeBincode};
+
+    use super::*;
+    use crate::test_support::datetime;
+
+    /// `ProcessStatus` as it was derived before `Custom` was added.
+    #[derive(Serialize)]
+    enum LegacyProcessStatus {
+        ApprovedWithConditions,
+        PendingAdditionalReview,
+        RevisionsReceived,
+    }
+
+    /// A `processing_state` row as written before `Custom` was added.
+    #[derive(Serialize)]
+    struct LegacyProcessingState {
+        processing_status: LegacyProcessStatus,
+        due_date: NaiveDateTime,
+        assigned_to: String,
+        last_modified: NaiveDateTime,
+        revision: u64,
+    }
+
+    fn encode<T: Serialize + 'static>(value: &T) -> Vec<u8> {
+        SerdeBincode::<T>::bytes_encode(value).unwrap().into_owned()
+    }
+
+    #[test]
+    fn built_in_stages_keep_their_bincode_layout() {
+        let pairs = [
+            (LegacyProcessStatus::ApprovedWithConditions, ProcessStatus::ApprovedWithConditions),
+            (LegacyProcessStatus::PendingAdditionalReview, ProcessStatus::PendingAdditionalReview),
+            (LegacyProcessStatus::RevisionsReceived, ProcessStatus::RevisionsReceived),
+        ];
+
+        for (legacy, current) in pairs {
+            let bytes = encode(&legacy);
+            assert_eq!(encode(&current), bytes, "{current}");
+            assert_eq!(SerdeBincode::<ProcessStatus>::bytes_decode(&bytes).unwrap(), current);
+        }
+    }
+
+    #[test]
+    fn legacy_processing_state_rows_still_decode() {
+        let legacy = LegacyProcessingState {
+            processing_status: LegacyProcessStatus::PendingAdditionalReview,
+            due_date: datetime("2026-10-01T00:00:00"),
+            assigned_to: "alice".to_string(),
+            last_modified: datetime("2026-09-01T00:00:00"),
+            revision: 3,
+        };
+
+        let row = SerdeBincode::<ProcessingStatusSchema>::bytes_decode(&encode(&legacy)).unwrap();
+        assert_eq!(
+            row,
+            ProcessingStatusSchema {
+                processing_status: ProcessStatus::PendingAdditionalReview,
+                due_date: legacy.due_date,
+                assigned_to: legacy.assigned_to,
+                last_modified: legacy.last_modified,
+                revision: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn custom_stages_round_trip_through_bincode() {
+        let stage = ProcessStatus::Custom("PlanCheck".to_string());
+        let bytes = encode(&stage);
+        assert_eq!(SerdeBincode::<ProcessStatus>::bytes_decode(&bytes).unwrap(), stage);
+    }
+
+    #[test]
+    fn stages_are_plain_strings_in_json() {
+        assert_eq!(serde_json::to_string(&ProcessStatus::RevisionsReceived).unwrap(), "\"RevisionsReceived\"");
+        assert_eq!(serde_json::to_string(&ProcessStatus::Custom("Plan Check".to_string())).unwrap(), "\"Plan Check\"");
+
+        let parsed: ProcessStatus = serde_json::from_str("\"revisionsreceived\"").unwrap();
+        assert_eq!(parsed, ProcessStatus::RevisionsReceived);
+        let parsed: ProcessStatus = serde_json::from_str("\" PlanCheck \"").unwrap();
+        assert_eq!(parsed, ProcessStatus::Custom("PlanCheck".to_string()));
+        assert!(serde_json::from_str::<ProcessStatus>("\"  \"").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Fixtures shared by the unit tests.
+
+use std::{path::PathBuf, sync::Arc};
+
+use chrono::NaiveDateTime;
+use heed::{Env, EnvOpenOptions};
+
+use crate::{
+    db_setup::setup_db,
+    helper_functions::index_record,
+    struct_definitions::{DBHandles, DBSchema, Status},
+};
+
+/// Parses `%Y-%m-%dT%H:%M:%S`, with or without a fraction.
+pub fn datetime(value: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").unwrap()
+}
+
+/// A live record in county `king`, opened, last updated and status updated
+/// at `opened`.
+pub fn record(permit_number: &str, client: &str, opened: NaiveDateTime) -> DBSchema {
+    DBSchema {
+        permit_link: format!("http://permits/{permit_number}"),
+        permit_number: permit_number.to_string(),
+        client: client.to_string(),
+        opened,
+        last_updated: opened,
+        status_updated: opened,
+        county: "king".to_string(),
+        county_status: Status::Active,
+        manual_status: Status::Active,
+        address: "1 Main St".to_string(),
+        normalized_address: "1 MAIN ST".to_string(),
+        revision: 1,
+    }
+}
+
+/// An environment in a directory of its own under the system temp dir,
+/// removed again on drop.
+pub struct TempEnv {
+    pub env: Arc<Env>,
+    dir: PathBuf,
+}
+
+impl TempEnv {
+    /// Opens an empty environment, with none of the server's databases yet.
+    pub fn open() -> TempEnv {
+        let dir = std::env::temp_dir().join(format!("actix-crud-api-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let env = unsafe { EnvOpenOptions::new().map_size(64 * 1024 * 1024).max_dbs(1000).open(&dir) }.unwrap();
+
+        TempEnv { env: Arc::new(env), dir }
+    }
+
+    /// Runs the same setup as the server: migrations, then every database.
+    pub fn set_up(&self) -> DBHandles {
+        setup_db(self.env.clone()).unwrap()
+    }
+
+    /// Writes `records` under `{opened:?}-{permit_number}` keys, indexed the
+    /// way a create indexes them.
+    pub fn insert(&self, db_handles: &DBHandles, records: &[DBSchema]) -> Vec<(String, DBSchema)> {
+        let mut wtxn = self.env.write_txn().unwrap();
+        let keyed: Vec<(String, DBSchema)> = records
+            .iter()
+            .map(|record| (format!("{:?}-{}", record.opened, record.permit_number), record.clone()))
+            .collect();
+        for (key, record) in &keyed {
+            db_handles.main_db.put(&mut wtxn, key, record).unwrap();
+            index_record(db_handles, &mut wtxn, key, record).unwrap();
+        }
+        wtxn.commit().unwrap();
+
+        keyed
+    }
+}
+
+impl Drop for TempEnv {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, NaiveDateTime};
+
+use crate::struct_definitions::{FilterError, Payments};
+
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+pub const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// Longest `last_N_days` accepted, to keep the range inside chrono's limits.
+const MAX_RELATIVE_DAYS: u64 = 36_500;
+
+pub enum ParsedDate {
+    Day(NaiveDate),
+    Instant(NaiveDateTime),
+}
+
+/// Parses a date given as `YYYY-MM-DD`, as a naive `%Y-%m-%dT%H:%M:%S%.f`
+/// timestamp, or as an RFC 3339 timestamp, which is converted to UTC to match
+/// how the records are stored.
+pub fn parse_date(value: &str) -> Option<ParsedDate> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, DATE_FORMAT) {
+        return Some(ParsedDate::Day(date));
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(value, DATETIME_FORMAT) {
+        return Some(ParsedDate::Instant(date));
+    }
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|date| ParsedDate::Instant(date.naive_utc()))
+}
+
+/// Like [`parse_date`], with a bare date taken as midnight.
+pub fn parse_datetime(value: &str) -> Option<NaiveDateTime> {
+    match parse_date(value)? {
+        ParsedDate::Day(date) => date.and_hms_opt(0, 0, 0),
+        ParsedDate::Instant(date) => Some(date),
+    }
+}
+
+pub fn date_error_message(value: &str) -> String {
+    format!("'{value}' is not a valid date, expected YYYY-MM-DD, {DATETIME_FORMAT} or an RFC 3339 timestamp")
+}
+
+/// Resolves a named range such as `last_30_days` to the first and last day it
+/// covers, both inclusive. `today` is passed in so callers agree on it.
+pub fn relative_range(value: &str, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    let first_of_month = today.with_day(1)?;
+    let first_of_quarter = first_of_month.with_month((today.month0() / 3) * 3 + 1)?;
+
+    match value {
+        "today" => Some((today, today)),
+        "yesterday" => {
+            let yesterday = today.checked_sub_days(Days::new(1))?;
+            Some((yesterday, yesterday))
+        }
+        "this_month" => Some((first_of_month, today)),
+        "last_month" => {
+            let start = first_of_month.checked_sub_months(Months::new(1))?;
+            Some((start, first_of_month.checked_sub_days(Days::new(1))?))
+        }
+        "this_quarter" => Some((first_of_quarter, today)),
+        "last_quarter" => {
+            let start = first_of_quarter.checked_sub_months(Months::new(3))?;
+            Some((start, first_of_quarter.checked_sub_days(Days::new(1))?))
+        }
+        "this_year" => Some((today.with_ordinal(1)?, today)),
+        "last_year" => {
+            let start = NaiveDate::from_ymd_opt(today.year() - 1, 1, 1)?;
+            Some((start, today.with_ordinal(1)?.checked_sub_days(Days::new(1))?))
+        }
+        _ => {
+            let days: u64 = value.strip_prefix("last_")?.strip_suffix("_days")?.parse().ok()?;
+            if days == 0 || days > MAX_RELATIVE_DAYS {
+                return None;
+            }
+            Some((today.checked_sub_days(Days::new(days - 1))?, today))
+        }
+    }
+}
+
+pub fn relative_range_error_message(value: &str) -> String {
+    format!(
+        "'{value}' is not a known range, expected today, yesterday, this_month, last_month, this_quarter, last_quarter, this_year, last_year or last_N_days"
+    )
+}
+
+/// The date-range endpoints need either both of `start_date` and `end_date`,
+/// or a relative `range`.
+pub fn require_date_range(params: &HashMap<String, String>) -> Result<(), FilterError> {
+    if params.contains_key("range") {
+        return Ok(());
+    }
+
+    match ["start_date", "end_date"].into_iter().find(|name| !params.contains_key(*name)) {
+        Some(missing) => Err(FilterError {
+            code: "invalid_value",
+            field: missing.to_string(),
+            message: "Both start_date and end_date must exist, or a relative range".to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Rejects a payment without a name or a status.
+pub fn validate_payment(payment: &Payments) -> Result<(), FilterError> {
+    let missing = [("payment", &payment.payment), ("status", &payment.status)]
+        .into_iter()
+        .find(|(_, value)| value.trim().is_empty());
+
+    match missing {
+        Some((field, _)) => Err(FilterError {
+            code: "invalid_value",
+            field: field.to_string(),
+            message: format!("{field} must not be empty"),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, DATE_FORMAT).unwrap()
+    }
+
+    #[test]
+    fn relative_ranges_resolve_against_today() {
+        let today = day("2026-05-15");
+        let cases = [
+            ("today", "2026-05-15", "2026-05-15"),
+            ("yesterday", "2026-05-14", "2026-05-14"),
+            ("this_month", "2026-05-01", "2026-05-15"),
+            ("last_month", "2026-04-01", "2026-04-30"),
+            ("this_quarter", "2026-04-01", "2026-05-15"),
+            ("last_quarter", "2026-01-01", "2026-03-31"),
+            ("this_year", "2026-01-01", "2026-05-15"),
+            ("last_year", "2025-01-01", "2025-12-31"),
+            ("last_1_days", "2026-05-15", "2026-05-15"),
+            ("last_7_days", "2026-05-09", "2026-05-15"),
+        ];
+
+        for (range, first, last) in cases {
+            assert_eq!(relative_range(range, today), Some((day(first), day(last))), "{range}");
+        }
+    }
+
+    #[test]
+    fn relative_ranges_cross_year_boundaries() {
+        let today = day("2026-01-10");
+        assert_eq!(relative_range("last_month", today), Some((day("2025-12-01"), day("2025-12-31"))));
+        assert_eq!(relative_range("last_quarter", today), Some((day("2025-10-01"), day("2025-12-31"))));
+        assert_eq!(relative_range("last_14_days", today), Some((day("2025-12-28"), day("2026-01-10"))));
+    }
+
+    #[test]
+    fn unknown_relative_ranges_are_rejected() {
+        let today = day("2026-05-15");
+        for range in ["", "tomorrow", "last_0_days", "last_36501_days", "last_-1_days", "last_x_days", "last_7_day"] {
+            assert_eq!(relative_range(range, today), None, "{range}");
+        }
+        assert!(relative_range("last_36500_days", today).is_some());
+    }
+
+    #[test]
+    fn parse_date_accepts_days_naive_and_rfc3339_timestamps() {
+        assert!(matches!(parse_date("2024-01-05"), Some(ParsedDate::Day(_))));
+        assert_eq!(
+            parse_datetime("2024-01-05T10:00:00.5"),
+            NaiveDate::from_ymd_opt(2024, 1, 5).and_then(|day| day.and_hms_milli_opt(10, 0, 0, 500))
+        );
+        assert_eq!(parse_datetime("2024-01-05T10:00:00+02:00"), parse_datetime("2024-01-05T08:00:00"));
+        assert_eq!(parse_datetime("2024-01-05"), parse_datetime("2024-01-05T00:00:00"));
+        assert!(parse_date("05/01/2024").is_none());
+    }
+}
@@ -0,0 +1,430 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, ops::Bound, str::FromStr};
+
+use chrono::NaiveDateTime;
+use heed::{Env, RoTxn, RwTxn};
+use serde_json::{Value, json};
+
+use crate::{
+    api_error::ApiError,
+    helper_functions::child_keys,
+    stages::is_known_stage,
+    struct_definitions::{
+        CurrentProcessing, DBHandles, DBSchema, FieldFilter, FilterError, FilterValue, Predicate, ProcessStatus,
+        ProcessingStatusSchema, RecordField,
+    },
+};
+
+/// `due_index` keys are `"{due_date:?}-{permit_number}"`, so they sort by
+/// due date. Only the current processing state of each permit is indexed.
+pub fn due_key(permit_number: &str, state: &ProcessingStatusSchema) -> String {
+    format!("{:?}-{permit_number}", state.due_date)
+}
+
+/// The permit's current processing state, the one `current_processing`
+/// points at.
+pub fn current_processing_state(
+    db_handles: &DBHandles,
+    rtxn: &RoTxn,
+    permit_number: &str,
+) -> heed::Result<Option<(String, ProcessingStatusSchema)>> {
+    let Some(current) = db_handles.current_processing.get(rtxn, permit_number)? else {
+        return Ok(None);
+    };
+    let state = db_handles.processing_state.get(rtxn, &current.key)?;
+
+    Ok(state.map(|state| (current.key, state)))
+}
+
+/// Drops the permit's current state from `current_processing` and
+/// `due_index`. Call before changing its processing states, then
+/// [`index_current_state`] once they are written.
+pub fn unindex_current_state(db_handles: &DBHandles, wtxn: &mut RwTxn, permit_number: &str) -> heed::Result<()> {
+    if let Some((_, state)) = current_processing_state(db_handles, wtxn, permit_number)? {
+        db_handles.due_index.delete(wtxn, &due_key(permit_number, &state))?;
+    }
+    db_handles.current_processing.delete(wtxn, permit_number)?;
+
+    Ok(())
+}
+
+/// Points `current_processing` at the state with the newest
+/// `last_modified`, the last of the permit's
+/// `"{permit_number}-{last_modified:?}"` keys, and indexes its due date.
+pub fn index_current_state(db_handles: &DBHandles, wtxn: &mut RwTxn, permit_number: &str) -> heed::Result<()> {
+    let Some(key) = child_keys(db_handles.processing_state, wtxn, permit_number)?.pop() else {
+        return Ok(());
+    };
+    if let Some(state) = db_handles.processing_state.get(wtxn, &key)? {
+        db_handles.due_index.put(wtxn, &due_key(permit_number, &state), permit_number)?;
+        let current = CurrentProcessing {
+            key,
+            processing_status: state.processing_status,
+        };
+        db_handles.current_processing.put(wtxn, permit_number, &current)?;
+    }
+
+    Ok(())
+}
+
+pub fn index_assignee(
+    db_handles: &DBHandles,
+    wtxn: &mut RwTxn,
+    key: &str,
+    state: &ProcessingStatusSchema,
+) -> heed::Result<()> {
+    let mut keys = db_handles.assignee_index.get(wtxn, &state.assigned_to)?.unwrap_or_default();
+    keys.insert(key.to_owned());
+    db_handles.assignee_index.put(wtxn, &state.assigned_to, &keys)
+}
+
+/// Takes a processing state off its assignee's list, as it was stored
+/// before a reassignment, re-key or delete.
+pub fn unindex_assignee(
+    db_handles: &DBHandles,
+    wtxn: &mut RwTxn,
+    key: &str,
+    state: &ProcessingStatusSchema,
+) -> heed::Result<()> {
+    if let Some(mut keys) = db_handles.assignee_index.get(wtxn, &state.assigned_to)? {
+        keys.remove(key);
+        if keys.is_empty() {
+            db_handles.assignee_index.delete(wtxn, &state.assigned_to)?;
+        } else {
+            db_handles.assignee_index.put(wtxn, &state.assigned_to, &keys)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Permit number of a `"{permit_number}-{last_modified:?}"` key. The
+/// timestamp holds two dashes, so the separator is the third from the end.
+fn permit_number_of(key: &str) -> Option<&str> {
+    key.rmatch_indices('-').nth(2).map(|(i, _)| &key[..i])
+}
+
+/// Rebuilds `current_processing` and `due_index` from
+/// `processing_state_db`, returning how many permits have a current state.
+pub fn rebuild_current_states(env: &Env, db_handles: &DBHandles) -> heed::Result<usize> {
+    let mut wtxn = env.write_txn()?;
+    let mut latest: HashMap<String, (String, ProcessingStatusSchema)> = HashMap::new();
+
+    for entry in db_handles.processing_state.iter(&wtxn)? {
+        let (key, state) = entry?;
+        let Some(permit_number) = permit_number_of(key) else {
+            continue;
+        };
+        // Keys come in order, so a later key for the same permit is newer.
+        latest.insert(permit_number.to_string(), (key.to_string(), state));
+    }
+
+    db_handles.due_index.clear(&mut wtxn)?;
+    db_handles.current_processing.clear(&mut wtxn)?;
+    for (permit_number, (key, state)) in &latest {
+        db_handles.due_index.put(&mut wtxn, &due_key(permit_number, state), permit_number)?;
+        let current = CurrentProcessing {
+            key: key.to_owned(),
+            processing_status: state.processing_status.clone(),
+        };
+        db_handles.current_processing.put(&mut wtxn, permit_number, &current)?;
+    }
+    wtxn.commit()?;
+
+    Ok(latest.len())
+}
+
+/// Rebuilds `assignee_index` from `processing_state_db`, returning how many
+/// assignees were indexed.
+pub fn rebuild_assignee_index(env: &Env, db_handles: &DBHandles) -> heed::Result<usize> {
+    let mut wtxn = env.write_txn()?;
+    let mut keys_by_assignee: HashMap<String, HashSet<String>> = HashMap::new();
+
+    for entry in db_handles.processing_state.iter(&wtxn)? {
+        let (key, state) = entry?;
+        keys_by_assignee.entry(state.assigned_to).or_default().insert(key.to_string());
+    }
+
+    db_handles.assignee_index.clear(&mut wtxn)?;
+    for (assignee, keys) in &keys_by_assignee {
+        db_handles.assignee_index.put(&mut wtxn, assignee, keys)?;
+    }
+    wtxn.commit()?;
+
+    Ok(keys_by_assignee.len())
+}
+
+/// A permit whose current processing state falls due in the scanned range.
+pub struct DueState {
+    pub permit_number: String,
+    pub key: String,
+    pub state: ProcessingStatusSchema,
+}
+
+/// Current processing states with a due date in `[from, to)`, soonest first.
+pub fn due_states(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    from: Option<NaiveDateTime>,
+    to: NaiveDateTime,
+) -> heed::Result<Vec<DueState>> {
+    let lower = from.map_or(Bound::Unbounded, |from| Bound::Included(format!("{from:?}")));
+    let upper = Bound::Excluded(format!("{to:?}"));
+    let bounds = (lower.as_ref().map(String::as_str), upper.as_ref().map(String::as_str));
+
+    let mut due = vec![];
+    for entry in db_handles.due_index.range(rtxn, &bounds)? {
+        let (_, permit_number) = entry?;
+        if let Some((key, state)) = current_processing_state(db_handles, rtxn, permit_number)? {
+            due.push(DueState {
+                permit_number: permit_number.to_string(),
+                key,
+                state,
+            });
+        }
+    }
+
+    Ok(due)
+}
+
+/// Groups due states by `assigned_to`, each group soonest due first.
+/// `days_overdue` is negative for states not yet due.
+pub fn group_by_assignee(due: Vec<DueState>, now: NaiveDateTime) -> BTreeMap<String, Vec<Value>> {
+    let mut groups: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+
+    for DueState { permit_number, key, state } in due {
+        groups.entry(state.assigned_to.clone()).or_default().push(json!({
+            "permit_number": permit_number,
+            "key": key,
+            "processing_status": state.processing_status,
+            "due_date": state.due_date,
+            "last_modified": state.last_modified,
+            "days_overdue": (now - state.due_date).num_days()
+        }));
+    }
+
+    groups
+}
+
+/// Parses a window such as `7d`, `12h` or `2w`.
+pub fn parse_within(value: &str) -> Option<chrono::Duration> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let amount: i64 = value[..value.len() - unit.len_utf8()].parse().ok().filter(|amount| *amount > 0)?;
+
+    match unit {
+        'h' => chrono::Duration::try_hours(amount),
+        'd' => chrono::Duration::try_days(amount),
+        'w' => chrono::Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+/// One permit in an assignee's queue: its current processing state and the
+/// `main_db` record it belongs to, when that still exists.
+pub struct QueueItem {
+    pub permit_number: String,
+    pub key: String,
+    pub state: ProcessingStatusSchema,
+    pub record: Option<(String, DBSchema)>,
+}
+
+impl QueueItem {
+    pub fn to_json(&self) -> Value {
+        let record = self.record.as_ref().map(|(key, record)| {
+            json!({
+                "key": key,
+                "client": record.client,
+                "county": record.county,
+                "county_status": record.county_status,
+                "manual_status": record.manual_status,
+                "address": record.address,
+                "opened": record.opened
+            })
+        });
+
+        json!({
+            "permit_number": self.permit_number,
+            "key": self.key,
+            "processing_status": self.state.processing_status,
+            "due_date": self.state.due_date,
+            "last_modified": self.state.last_modified,
+            "record": record
+        })
+    }
+}
+
+/// Parses the queue's `sort`, which only orders by `due_date`. Returns
+/// whether it is descending.
+pub fn parse_queue_sort(sort: Option<&str>) -> Result<bool, ApiError> {
+    let Some(sort) = sort.map(str::trim).filter(|sort| !sort.is_empty()) else {
+        return Ok(false);
+    };
+    let (name, direction) = sort.split_once(':').unwrap_or((sort, "asc"));
+
+    if name.trim() != "due_date" {
+        return Err(ApiError::Validation {
+            code: "unknown_field",
+            field: Some("sort".to_string()),
+            message: format!("Cannot sort the queue by '{}', expected due_date", name.trim()),
+        });
+    }
+    match direction.trim() {
+        "asc" => Ok(false),
+        "desc" | "dsc" => Ok(true),
+        other => Err(ApiError::validation(
+            "sort",
+            format!("'{other}' is not a sort direction, expected asc or desc"),
+        )),
+    }
+}
+
+/// The permits whose current processing state is assigned to `assignee`,
+/// ordered by due date. A permit handed on to someone else in a later state
+/// drops out of the queue, even though its older states stay indexed.
+pub fn assignee_queue(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    assignee: &str,
+    descending: bool,
+) -> heed::Result<Vec<QueueItem>> {
+    let keys = db_handles.assignee_index.get(rtxn, assignee)?.unwrap_or_default();
+    let permit_numbers: HashSet<&str> = keys.iter().filter_map(|key| permit_number_of(key)).collect();
+
+    let mut queue = vec![];
+    for permit_number in permit_numbers {
+        let Some((key, state)) = current_processing_state(db_handles, rtxn, permit_number)? else {
+            continue;
+        };
+        if state.assigned_to != assignee {
+            continue;
+        }
+
+        let record = match db_handles.permit_index.get(rtxn, permit_number)? {
+            Some(record_key) => db_handles
+                .main_db
+                .get(rtxn, record_key)?
+                .map(|record| (record_key.to_string(), record)),
+            None => None,
+        };
+        queue.push(QueueItem {
+            permit_number: permit_number.to_string(),
+            key,
+            state,
+            record,
+        });
+    }
+
+    queue.sort_by(|a, b| {
+        let ordering = a.state.due_date.cmp(&b.state.due_date);
+        let ordering = if descending { ordering.reverse() } else { ordering };
+        ordering.then_with(|| a.permit_number.cmp(&b.permit_number))
+    });
+
+    Ok(queue)
+}
+
+fn is_processing_status_param(name: &str) -> bool {
+    name == "processing_status" || name.starts_with("processing_status.")
+}
+
+/// The `processing_status` filter parameters, which the listing endpoints
+/// leave out of [`RecordFilter::parse`](crate::struct_definitions::RecordFilter).
+pub fn processing_status_params(params: &HashMap<String, String>) -> Vec<&str> {
+    params
+        .keys()
+        .map(String::as_str)
+        .filter(|name| is_processing_status_param(name))
+        .collect()
+}
+
+/// Turns `processing_status` / `processing_status.in` into a filter on the
+/// permit numbers whose current processing state has one of the statuses.
+/// Permits without processing states never match.
+pub fn processing_status_filter(
+    rtxn: &RoTxn,
+    db_handles: &DBHandles,
+    params: &HashMap<String, String>,
+) -> Result<Option<FieldFilter>, ApiError> {
+    let mut allowed: Option<HashSet<ProcessStatus>> = None;
+
+    let mut names: Vec<&str> = processing_status_params(params);
+    names.sort();
+    for name in names {
+        let value = params[name].trim();
+        let values = match name {
+            "processing_status" => vec![value],
+            "processing_status.in" => value.split(',').map(str::trim).collect(),
+            _ => {
+                let op = name.trim_start_matches("processing_status.");
+                return Err(FilterError {
+                    code: "unsupported_operator",
+                    field: name.to_string(),
+                    message: format!("'{op}' is not supported on 'processing_status'"),
+                }
+                .into());
+            }
+        };
+        let mut statuses = HashSet::new();
+        for value in values {
+            let status = ProcessStatus::from_str(value).ok();
+            match status {
+                Some(status) if is_known_stage(rtxn, db_handles, &status)? => {
+                    statuses.insert(status);
+                }
+                _ => {
+                    return Err(ApiError::validation(
+                        name,
+                        format!("'{value}' is not a processing status of any county"),
+                    ));
+                }
+            }
+        }
+
+        allowed = Some(match allowed {
+            Some(current) => current.intersection(&statuses).cloned().collect(),
+            None => statuses,
+        });
+    }
+
+    let Some(allowed) = allowed else {
+        return Ok(None);
+    };
+
+    let mut permit_numbers = vec![];
+    for entry in db_handles.current_processing.iter(rtxn)? {
+        let (permit_number, current) = entry?;
+        if allowed.contains(&current.processing_status) {
+            permit_numbers.push(FilterValue::Text(permit_number.to_string()));
+        }
+    }
+
+    Ok(Some(FieldFilter {
+        field: RecordField::PermitNumber,
+        predicate: Predicate::In(permit_numbers),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_within_reads_hours_days_and_weeks() {
+        assert_eq!(parse_within("12h"), chrono::Duration::try_hours(12));
+        assert_eq!(parse_within("7d"), chrono::Duration::try_days(7));
+        assert_eq!(parse_within(" 2w "), chrono::Duration::try_weeks(2));
+    }
+
+    #[test]
+    fn parse_within_rejects_bad_windows() {
+        for value in ["", "d", "7", "0d", "-1d", "7x", "1.5d", "7 d", "99999999999999999999w", "9223372036854775807w"] {
+            assert_eq!(parse_within(value), None, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn permit_number_of_strips_the_timestamp() {
+        assert_eq!(permit_number_of("P1-2024-01-05T10:00:00"), Some("P1"));
+        assert_eq!(permit_number_of("BLD-2024-001-2024-01-05T10:00:00.500"), Some("BLD-2024-001"));
+    }
+}